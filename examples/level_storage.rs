@@ -0,0 +1,77 @@
+//! Compares the per-level hash maps with the single flat table and with directly addressed top
+//! levels, under SipHash and the integer hasher, on random keys spread over a 64 bit universe and
+//! packed into a 20 bit one.
+//!
+//! Run with `cargo run --release --example level_storage`.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+use xfast::{BuildIntHasher, Xfast};
+
+const KEYS: usize = 1 << 17;
+const ROUNDS: usize = 3;
+
+// xorshift keys, the same on every run
+fn random_keys(count: usize, mut seed: u64) -> Vec<u64> {
+    (0..count).map(|_| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    }).collect()
+}
+
+// fastest of a few rounds of inserting the keys, querying the successors of the probes and
+// removing the keys, in nanoseconds per operation
+fn measure<S: BuildHasher + Clone>(empty: impl Fn() -> Xfast<u64, u64, S>, keys: &[u64], probes: &[u64]) -> [f64; 3] {
+    let mut best = [Duration::MAX; 3];
+    let mut checksum = 0;
+    for _ in 0..ROUNDS {
+        let mut trie = empty();
+        let start = Instant::now();
+        for &key in keys {
+            trie.insert(key, key);
+        }
+        let inserted = Instant::now();
+        for &probe in probes {
+            checksum ^= trie.successor(probe).map_or(0, |(key, _)| key);
+        }
+        let queried = Instant::now();
+        for &key in keys {
+            trie.remove(key);
+        }
+        let removed = Instant::now();
+        let times = [inserted - start, queried - inserted, removed - queried];
+        for (best, time) in best.iter_mut().zip(times.iter()) {
+            *best = (*best).min(*time);
+        }
+    }
+    assert!(checksum != 1, "queries optimized out");
+    let per_op = |time: Duration, count: usize| time.as_nanos() as f64 / count as f64;
+    [per_op(best[0], keys.len()), per_op(best[1], probes.len()), per_op(best[2], keys.len())]
+}
+
+fn main() {
+    let sparse = random_keys(KEYS, 0x2545_f491_4f6c_dd1d);
+    let sparse_probes = random_keys(KEYS, 0x9e37_79b9_7f4a_7c15);
+    let dense: Vec<u64> = sparse.iter().map(|key| key >> 44).collect();
+    let dense_probes: Vec<u64> = sparse_probes.iter().map(|key| key >> 44).collect();
+    let workloads = [("u64 universe", u64::MAX, &sparse, &sparse_probes), ("2^20 universe", (1 << 20) - 1, &dense, &dense_probes)];
+
+    println!("{} random keys, ns per operation", KEYS);
+    println!("{:<16}{:<24}{:>10}{:>12}{:>10}", "keys", "storage", "insert", "successor", "remove");
+    for &(workload, range, keys, probes) in workloads.iter() {
+        let rows = [
+            ("level maps, SipHash", measure(|| Xfast::with_hasher(range, RandomState::new()), keys, probes)),
+            ("flat table, SipHash", measure(|| Xfast::with_hasher(range, RandomState::new()).with_flat_table(), keys, probes)),
+            ("level maps, IntHasher", measure(|| Xfast::with_hasher(range, BuildIntHasher::with_seed(1)), keys, probes)),
+            ("flat table, IntHasher", measure(|| Xfast::with_hasher(range, BuildIntHasher::with_seed(1)).with_flat_table(), keys, probes)),
+            ("16 direct, IntHasher", measure(|| Xfast::with_hasher(range, BuildIntHasher::with_seed(1)).with_direct_levels(16), keys, probes)),
+        ];
+        for (name, [insert, successor, remove]) in rows.iter() {
+            println!("{:<16}{:<24}{:>10.0}{:>12.0}{:>10.0}", workload, name, insert, successor, remove);
+        }
+    }
+}
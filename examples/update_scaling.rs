@@ -0,0 +1,90 @@
+//! Times inserts and removals in universes from 8 up to 64 bits wide. With the descendant ptrs
+//! fixed in O(1) per level, an update costs O(log U), so the time per level of the trie should stay
+//! about flat as the universe grows.
+//!
+//! Random keys rarely make the descendant ptrs costly to recompute, so a second workload toggles
+//! the key 0 in a comb: for every other level the path of 0 has a node without a right child, whose
+//! left child leads down a chain of right children to a single leaf. Recomputing the descendant
+//! ptrs from the children walks every one of these chains, O(log^2 U) in total.
+//!
+//! Run with `cargo run --release --example update_scaling`.
+
+use std::time::{Duration, Instant};
+
+use xfast::{BuildIntHasher, Xfast};
+
+const KEYS: usize = 1 << 15;
+const TOGGLES: usize = 1 << 14;
+const ROUNDS: usize = 3;
+
+// xorshift keys, the same on every run
+fn random_keys(count: usize, mut seed: u64) -> Vec<u64> {
+    (0..count).map(|_| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    }).collect()
+}
+
+// the keys 0..01..1 made of `level + 1` zeros followed by ones, for every other level
+fn comb(bits: u32) -> Vec<u64> {
+    (0..bits - 1).step_by(2).map(|level| u64::MAX >> (64 - bits + level + 1)).collect()
+}
+
+fn main() {
+    let random = random_keys(KEYS, 0x2545_f491_4f6c_dd1d);
+    println!("{} random keys, ns per operation", KEYS);
+    println!("{:>6}{:>10}{:>10}{:>18}{:>18}", "bits", "insert", "remove", "insert per level", "remove per level");
+    for bits in (8..=64).step_by(8) {
+        let keys: Vec<u64> = random.iter().map(|key| key >> (64 - bits)).collect();
+        let range = u64::MAX >> (64 - bits);
+        let (mut insert, mut remove) = (Duration::MAX, Duration::MAX);
+        for _ in 0..ROUNDS {
+            let mut trie: Xfast<u64, u64, _> = Xfast::with_hasher(range, BuildIntHasher::with_seed(1));
+            let start = Instant::now();
+            for &key in keys.iter() {
+                trie.insert(key, key);
+            }
+            let inserted = Instant::now();
+            for &key in keys.iter() {
+                trie.remove(key);
+            }
+            assert!(trie.is_empty());
+            insert = insert.min(inserted - start);
+            remove = remove.min(inserted.elapsed());
+        }
+        let per_op = |time: Duration| time.as_nanos() as f64 / KEYS as f64;
+        println!("{:>6}{:>10.0}{:>10.0}{:>18.1}{:>18.1}", bits, per_op(insert), per_op(remove),
+            per_op(insert) / bits as f64, per_op(remove) / bits as f64);
+    }
+
+    println!();
+    println!("0 toggled in a comb, ns per operation");
+    println!("{:>6}{:>10}{:>10}{:>18}{:>18}", "bits", "insert", "remove", "insert per level", "remove per level");
+    for bits in (8..=64).step_by(8) {
+        let range = u64::MAX >> (64 - bits);
+        let mut trie: Xfast<u64, u64, _> = Xfast::with_hasher(range, BuildIntHasher::with_seed(1));
+        for key in comb(bits) {
+            trie.insert(key, key);
+        }
+        let (mut insert, mut remove) = (Duration::MAX, Duration::MAX);
+        for _ in 0..ROUNDS {
+            let (mut inserting, mut removing) = (Duration::ZERO, Duration::ZERO);
+            for _ in 0..TOGGLES {
+                let start = Instant::now();
+                trie.insert(0, 0);
+                let inserted = Instant::now();
+                trie.remove(0);
+                inserting += inserted - start;
+                removing += inserted.elapsed();
+            }
+            insert = insert.min(inserting);
+            remove = remove.min(removing);
+        }
+        assert_eq!(trie.len(), comb(bits).len());
+        let per_op = |time: Duration| time.as_nanos() as f64 / TOGGLES as f64;
+        println!("{:>6}{:>10.0}{:>10.0}{:>18.1}{:>18.1}", bits, per_op(insert), per_op(remove),
+            per_op(insert) / bits as f64, per_op(remove) / bits as f64);
+    }
+}
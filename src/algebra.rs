@@ -0,0 +1,407 @@
+//! Set algebra between the keys of two tries.
+//!
+//! The operations yield keys in ascending order and only look at the keys of the tries, so the
+//! values of the two tries can be of different types. Both tries need the same number of levels.
+
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::iter::{FusedIterator, Peekable};
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+
+use crate::arena::Arena;
+use crate::{Node, TrieNode, Xfast, XfastIter, XfastKey};
+
+/// Iterator around the keys present in either of two tries, in ascending order
+pub struct Union<'a, K: XfastKey, V, W> {
+    left: Peekable<XfastIter<'a, K, V>>,
+    right: Peekable<XfastIter<'a, K, W>>,
+}
+
+/// Iterator around the keys present in both of two tries, in ascending order
+pub struct Intersection<'a, K, V, W, S = RandomState> {
+    left: &'a Xfast<K, V, S>,
+    right: &'a Xfast<K, W, S>,
+    // smallest key which can be yielded next, None once the keys run out
+    lower_bound: Option<u128>,
+}
+
+/// Iterator around the keys present in one trie and absent from another, in ascending order
+pub struct Difference<'a, K, V, W, S = RandomState> {
+    iter: XfastIter<'a, K, V>,
+    other: &'a Xfast<K, W, S>,
+}
+
+/// Iterator around the keys present in exactly one of two tries, in ascending order
+pub struct SymmetricDifference<'a, K: XfastKey, V, W> {
+    left: Peekable<XfastIter<'a, K, V>>,
+    right: Peekable<XfastIter<'a, K, W>>,
+}
+
+/// Iterator around the ranges of keys absent from a trie within some bounds, in ascending order
+pub struct Gaps<'a, K, V> {
+    nodes: &'a Arena<TrieNode>,
+    // start of the next gap and the first leaf which isn't before it, None once done
+    start: Option<u128>,
+    leaf: Option<Node>,
+    end: u128,
+    trie_type: PhantomData<(K, &'a V)>,
+}
+
+impl<K: XfastKey, V, S: BuildHasher> Xfast<K, V, S> {
+
+    fn assert_same_levels<W>(&self, other: &Xfast<K, W, S>) {
+        assert_eq!(self.nr_levels, other.nr_levels, "set operations need tries with the same number of levels");
+    }
+
+    /// Returns an iterator around the keys present in either `self` or `other`.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let odd: Xfast<u32, ()> = Xfast::from_sorted_iter(31, vec![(1, ()), (3, ()), (5, ())]);
+    ///     let squares: Xfast<u32, &str> = Xfast::from_sorted_iter(31, vec![(1, "one"), (4, "four")]);
+    ///     assert_eq!(odd.union(&squares).collect::<Vec<_>>(), vec![1, 3, 4, 5]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the tries don't have the same number of levels.
+    pub fn union<'a, W>(&'a self, other: &'a Xfast<K, W, S>) -> Union<'a, K, V, W> {
+        self.assert_same_levels(other);
+        Union {
+            left: self.iter().peekable(),
+            right: other.iter().peekable(),
+        }
+    }
+
+    /// Returns an iterator around the keys present in both `self` and `other`.
+    ///
+    /// The iterator alternates successor searches between the tries, so a small trie against a
+    /// large one costs O(s log log U) for the s keys of the smaller one.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let odd: Xfast<u32, ()> = Xfast::from_sorted_iter(31, vec![(1, ()), (3, ()), (5, ())]);
+    ///     let squares: Xfast<u32, &str> = Xfast::from_sorted_iter(31, vec![(1, "one"), (4, "four")]);
+    ///     assert_eq!(odd.intersection(&squares).collect::<Vec<_>>(), vec![1]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the tries don't have the same number of levels.
+    pub fn intersection<'a, W>(&'a self, other: &'a Xfast<K, W, S>) -> Intersection<'a, K, V, W, S> {
+        self.assert_same_levels(other);
+        Intersection {
+            left: self,
+            right: other,
+            lower_bound: Some(0),
+        }
+    }
+
+    /// Returns an iterator around the keys present in `self` and absent from `other`.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let odd: Xfast<u32, ()> = Xfast::from_sorted_iter(31, vec![(1, ()), (3, ()), (5, ())]);
+    ///     let squares: Xfast<u32, &str> = Xfast::from_sorted_iter(31, vec![(1, "one"), (4, "four")]);
+    ///     assert_eq!(odd.difference(&squares).collect::<Vec<_>>(), vec![3, 5]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the tries don't have the same number of levels.
+    pub fn difference<'a, W>(&'a self, other: &'a Xfast<K, W, S>) -> Difference<'a, K, V, W, S> {
+        self.assert_same_levels(other);
+        Difference {
+            iter: self.iter(),
+            other,
+        }
+    }
+
+    /// Returns an iterator around the keys present in exactly one of `self` and `other`.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let odd: Xfast<u32, ()> = Xfast::from_sorted_iter(31, vec![(1, ()), (3, ()), (5, ())]);
+    ///     let squares: Xfast<u32, &str> = Xfast::from_sorted_iter(31, vec![(1, "one"), (4, "four")]);
+    ///     assert_eq!(odd.symmetric_difference(&squares).collect::<Vec<_>>(), vec![3, 4, 5]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the tries don't have the same number of levels.
+    pub fn symmetric_difference<'a, W>(&'a self, other: &'a Xfast<K, W, S>) -> SymmetricDifference<'a, K, V, W> {
+        self.assert_same_levels(other);
+        SymmetricDifference {
+            left: self.iter().peekable(),
+            right: other.iter().peekable(),
+        }
+    }
+
+    /// Removes the keys absent from `other`, keeping the intersection of the tries in `self`.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut odd: Xfast<u32, ()> = Xfast::from_sorted_iter(31, vec![(1, ()), (3, ()), (5, ())]);
+    ///     let squares: Xfast<u32, &str> = Xfast::from_sorted_iter(31, vec![(1, "one"), (4, "four")]);
+    ///     odd.retain_in(&squares);
+    ///     assert_eq!(odd.iter().map(|(key, _)| key).collect::<Vec<_>>(), vec![1]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the tries don't have the same number of levels.
+    pub fn retain_in<W>(&mut self, other: &Xfast<K, W, S>) {
+        self.assert_same_levels(other);
+        let mut leaf = self.first_leaf();
+        while let Some(node) = leaf {
+            leaf = self.nodes[node].right;
+            if other.leaf_node(self.nodes[node].key).is_none() {
+                self.remove_leaf(node);
+            }
+        }
+    }
+
+    /// Inserts the keys of `other` absent from `self` along with a clone of their values, keeping
+    /// the union of the tries in `self`. Keys present in both keep the value of `self`.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<u32, &str> = Xfast::from_sorted_iter(31, vec![(1, "one"), (3, "three")]);
+    ///     let other: Xfast<u32, &str> = Xfast::from_sorted_iter(31, vec![(1, "uno"), (4, "four")]);
+    ///     test_trie.extend_from(&other);
+    ///     assert_eq!(test_trie.iter().collect::<Vec<_>>(), vec![(1, &"one"), (3, &"three"), (4, &"four")]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the tries don't have the same number of levels.
+    pub fn extend_from(&mut self, other: &Xfast<K, V, S>)
+    where
+        V: Clone,
+    {
+        self.assert_same_levels(other);
+        for (key, value) in other.iter() {
+            if self.leaf_node(key.to_bits()).is_none() {
+                self.insert_leaf(key.to_bits(), value.clone());
+            }
+        }
+    }
+
+    /// Returns an iterator around the ranges of keys within `bounds` which are absent from the trie.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let test_trie: Xfast<u32, ()> = Xfast::from_sorted_iter(31, vec![(3, ()), (4, ()), (9, ())]);
+    ///     assert_eq!(test_trie.complement_within(0..=10).collect::<Vec<_>>(), vec![0..=2, 5..=8, 10..=10]);
+    ///     assert_eq!(test_trie.complement_within(3..5).count(), 0);
+    /// ```
+    pub fn complement_within<R: RangeBounds<K>>(&self, bounds: R) -> Gaps<'_, K, V> {
+        let start = match bounds.start_bound() {
+            Bound::Included(start) => Some(start.to_bits()),
+            Bound::Excluded(start) => start.to_bits().checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let end = match bounds.end_bound() {
+            Bound::Included(end) => Some(end.to_bits()),
+            Bound::Excluded(end) => end.to_bits().checked_sub(1),
+            Bound::Unbounded => Some(self.max_key()),
+        };
+        // keys beyond the range of the trie are never stored, but can't be yielded either
+        let (start, end) = match (start, end) {
+            (Some(start), Some(end)) if start <= end && start <= self.max_key() => (start, end.min(self.max_key())),
+            _ => (1, 0),
+        };
+        Gaps {
+            nodes: &self.nodes,
+            start: if start <= end { Some(start) } else { None },
+            leaf: self.successor_node(start),
+            end,
+            trie_type: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V, W> Iterator for Union<'a, K, V, W> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let left = self.left.peek().map(|&(key, _)| key);
+        let right = self.right.peek().map(|&(key, _)| key);
+        match (left, right) {
+            (Some(left), Some(right)) => {
+                if left <= right {
+                    self.left.next();
+                }
+                if right <= left {
+                    self.right.next();
+                }
+                Some(left.min(right))
+            }
+            (Some(_), None) => self.left.next().map(|(key, _)| key),
+            (None, _) => self.right.next().map(|(key, _)| key),
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V, W> FusedIterator for Union<'a, K, V, W> {}
+
+impl<'a, K: XfastKey, V, W, S: BuildHasher> Iterator for Intersection<'a, K, V, W, S> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut bound = self.lower_bound?;
+        loop {
+            // every round moves past a key of each trie, so the smaller one bounds the rounds
+            let left = self.left.successor_node(bound).map(|node| self.left.nodes[node].key);
+            let right = left.and_then(|left| self.right.successor_node(left)).map(|node| self.right.nodes[node].key);
+            match (left, right) {
+                (Some(left), Some(right)) if left == right => {
+                    self.lower_bound = left.checked_add(1).filter(|&next| next <= self.left.max_key());
+                    return Some(K::from_bits(left));
+                }
+                (Some(_), Some(right)) => bound = right,
+                _ => {
+                    self.lower_bound = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V, W, S: BuildHasher> FusedIterator for Intersection<'a, K, V, W, S> {}
+
+impl<'a, K: XfastKey, V, W, S: BuildHasher> Iterator for Difference<'a, K, V, W, S> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let other = self.other;
+        self.iter.by_ref().map(|(key, _)| key).find(|key| other.leaf_node(key.to_bits()).is_none())
+    }
+}
+
+impl<'a, K: XfastKey, V, W, S: BuildHasher> FusedIterator for Difference<'a, K, V, W, S> {}
+
+impl<'a, K: XfastKey, V, W> Iterator for SymmetricDifference<'a, K, V, W> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let left = self.left.peek().map(|&(key, _)| key);
+            let right = self.right.peek().map(|&(key, _)| key);
+            match (left, right) {
+                (Some(left), Some(right)) => match left.cmp(&right) {
+                    Ordering::Less => return self.left.next().map(|(key, _)| key),
+                    Ordering::Greater => return self.right.next().map(|(key, _)| key),
+                    // keys present in both are skipped
+                    Ordering::Equal => {
+                        self.left.next();
+                        self.right.next();
+                    }
+                },
+                (Some(_), None) => return self.left.next().map(|(key, _)| key),
+                (None, _) => return self.right.next().map(|(key, _)| key),
+            }
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V, W> FusedIterator for SymmetricDifference<'a, K, V, W> {}
+
+impl<'a, K: XfastKey, V> Iterator for Gaps<'a, K, V> {
+    type Item = RangeInclusive<K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.start?;
+            let key = self.leaf.map(|node| self.nodes[node].key).filter(|&key| key <= self.end);
+            match key {
+                // the rest of the bounds is free
+                None => {
+                    self.start = None;
+                    return Some(K::from_bits(start)..=K::from_bits(self.end));
+                }
+                Some(key) => {
+                    self.start = key.checked_add(1).filter(|&next| next <= self.end);
+                    self.leaf = self.leaf.and_then(|node| self.nodes[node].right);
+                    if key > start {
+                        return Some(K::from_bits(start)..=K::from_bits(key - 1));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V> FusedIterator for Gaps<'a, K, V> {}
+
+#[cfg(test)]
+mod test {
+    use crate::Xfast;
+
+    fn init() -> (Xfast<u16, u16>, Xfast<u16, ()>) {
+        let multiples_of_3 = Xfast::from_sorted_iter(63, (0..64).step_by(3).map(|key| (key, key)));
+        let multiples_of_4 = Xfast::from_sorted_iter(63, (0..64).step_by(4).map(|key| (key, ())));
+        (multiples_of_3, multiples_of_4)
+    }
+
+    #[test]
+    fn lazy_operations() -> Result<(), String> {
+        let (threes, fours) = init();
+        let expected = |keep: fn(bool, bool) -> bool| -> Vec<u16> {
+            (0..64).filter(|key| keep(key % 3 == 0, key % 4 == 0)).collect()
+        };
+        let union: Vec<u16> = threes.union(&fours).collect();
+        let intersection: Vec<u16> = threes.intersection(&fours).collect();
+        let difference: Vec<u16> = fours.difference(&threes).collect();
+        let symmetric_difference: Vec<u16> = threes.symmetric_difference(&fours).collect();
+        if union == expected(|three, four| three || four) && intersection == expected(|three, four| three && four)
+            && difference == expected(|three, four| four && !three) && symmetric_difference == expected(|three, four| three != four) {
+            Ok(())
+        }
+        else {
+            Err(format!("Set operations are wrong, got {:?} and {:?}", intersection, symmetric_difference))
+        }
+    }
+
+    #[test]
+    fn in_place_operations() -> Result<(), String> {
+        let (mut threes, fours) = init();
+        let mut sixes = Xfast::from_sorted_iter(63, (0..64).step_by(6).map(|key| (key, key)));
+        threes.retain_in(&fours);
+        sixes.extend_from(&threes);
+        let retained: Vec<u16> = threes.iter().map(|(key, _)| key).collect();
+        let extended: Vec<u16> = sixes.iter().map(|(key, _)| key).collect();
+        if retained == vec![0, 12, 24, 36, 48, 60] && extended == vec![0, 6, 12, 18, 24, 30, 36, 42, 48, 54, 60]
+            && threes.successor(13) == Some((24, &24)) {
+            Ok(())
+        }
+        else {
+            Err(format!("In-place operations are wrong, got {:?} and {:?}", retained, extended))
+        }
+    }
+
+    #[test]
+    fn gaps() -> Result<(), String> {
+        let (_, fours) = init();
+        let gaps: Vec<_> = fours.complement_within(2..=13).collect();
+        let edges: Vec<_> = fours.complement_within(60..).collect();
+        if gaps == vec![2..=3, 5..=7, 9..=11, 13..=13] && edges == vec![61..=63] && fours.complement_within(8..=8).count() == 0 {
+            Ok(())
+        }
+        else {
+            Err(format!("Gaps are wrong, got {:?} and {:?}", gaps, edges))
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "same number of levels")]
+    fn different_levels() {
+        let (threes, _) = init();
+        let wider: Xfast<u16, ()> = Xfast::new(127);
+        threes.union(&wider).count();
+    }
+}
@@ -0,0 +1,199 @@
+//! Index-based storage for the trie nodes.
+//!
+//! Nodes are kept in a slab and addressed by their slot index. Vacated slots are chained into a
+//! free list so that later allocations reuse them instead of growing the slab.
+//!
+//! The values of the leaves are kept apart from the nodes, at the slot index of their leaf, so that
+//! internal nodes have no room for a value and the nodes of a set carry nothing but their links.
+
+use std::fmt;
+use std::mem::{self, MaybeUninit};
+use std::ops::{Index, IndexMut};
+
+#[derive(Debug, Clone)]
+enum Slot<T> {
+    Occupied(T),
+    // index of the next vacant slot in the free list
+    Vacant(Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free_head: Option<usize>,
+    len: usize,
+}
+
+// Values stored at the slot indices of the leaves holding them
+pub(crate) struct LeafValues<V> {
+    values: Vec<MaybeUninit<V>>,
+    // bit `index % 64` of word `index / 64` is set when the slot at `index` holds a value
+    live: Vec<u64>,
+}
+
+// Raw view of the leaf values. Iterators use it to hand out mutable references to the values of
+// distinct leaves while they keep following the links between the leaves.
+pub(crate) struct RawLeafValues<V> {
+    values: *mut MaybeUninit<V>,
+    live: *const u64,
+    len: usize,
+}
+
+impl<T> Arena<T> {
+    pub(crate) fn new() -> Self {
+        Arena {
+            slots: Vec::new(),
+            free_head: None,
+            len: 0,
+        }
+    }
+
+    // number of occupied slots
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    // number of slots, occupied or vacant
+    pub(crate) fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    // store `value` in a vacant slot (or a new one) and return its index
+    pub(crate) fn alloc(&mut self, value: T) -> usize {
+        self.len += 1;
+        match self.free_head {
+            Some(index) => {
+                if let Slot::Vacant(next) = self.slots[index] {
+                    self.free_head = next;
+                }
+                self.slots[index] = Slot::Occupied(value);
+                index
+            }
+            None => {
+                self.slots.push(Slot::Occupied(value));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    // vacate the slot at `index` and hand back the value stored in it
+    pub(crate) fn free(&mut self, index: usize) -> T {
+        match self.slots[index] {
+            Slot::Occupied(_) => {}
+            Slot::Vacant(_) => panic!("arena slot {} freed twice", index),
+        }
+        let slot = mem::replace(&mut self.slots[index], Slot::Vacant(self.free_head));
+        self.free_head = Some(index);
+        self.len -= 1;
+        match slot {
+            Slot::Occupied(value) => value,
+            Slot::Vacant(_) => unreachable!(),
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        match self.slots.get(index) {
+            Some(Slot::Occupied(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        match self.slots.get_mut(index) {
+            Some(Slot::Occupied(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<V> LeafValues<V> {
+    pub(crate) fn new() -> Self {
+        LeafValues {
+            values: Vec::new(),
+            live: Vec::new(),
+        }
+    }
+
+    fn is_live(&self, index: usize) -> bool {
+        self.live.get(index / 64).is_some_and(|word| word & (1 << (index % 64)) != 0)
+    }
+
+    // store `value` for the leaf at `index`, which must not hold one yet
+    pub(crate) fn insert(&mut self, index: usize, value: V) {
+        assert!(!self.is_live(index), "leaf {} already holds a value", index);
+        if self.values.len() <= index {
+            self.values.resize_with(index + 1, MaybeUninit::uninit);
+            self.live.resize(index / 64 + 1, 0);
+        }
+        self.values[index].write(value);
+        self.live[index / 64] |= 1 << (index % 64);
+    }
+
+    // take the value out of the leaf at `index`
+    pub(crate) fn remove(&mut self, index: usize) -> V {
+        assert!(self.is_live(index), "leaf without a value");
+        self.live[index / 64] &= !(1 << (index % 64));
+        // the slot was live, and it is vacant from now on
+        unsafe { self.values[index].assume_init_read() }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> &V {
+        assert!(self.is_live(index), "leaf without a value");
+        unsafe { self.values[index].assume_init_ref() }
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> &mut V {
+        assert!(self.is_live(index), "leaf without a value");
+        unsafe { self.values[index].assume_init_mut() }
+    }
+
+    pub(crate) fn as_raw(&mut self) -> RawLeafValues<V> {
+        RawLeafValues {
+            values: self.values.as_mut_ptr(),
+            live: self.live.as_ptr(),
+            len: self.values.len(),
+        }
+    }
+}
+
+impl<V> RawLeafValues<V> {
+    // Safety: the values must stay borrowed, neither moved nor resized, for `'a` and no other
+    // reference to the value at `index` may be alive during `'a`.
+    pub(crate) unsafe fn get_mut<'a>(&self, index: usize) -> &'a mut V {
+        assert!(index < self.len && *self.live.add(index / 64) & (1 << (index % 64)) != 0, "leaf without a value");
+        (*self.values.add(index)).assume_init_mut()
+    }
+}
+
+impl<V> Drop for LeafValues<V> {
+    fn drop(&mut self) {
+        if !mem::needs_drop::<V>() {
+            return;
+        }
+        for index in 0..self.values.len() {
+            if self.is_live(index) {
+                unsafe { self.values[index].assume_init_drop() };
+            }
+        }
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for LeafValues<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries((0..self.values.len()).filter(|&index| self.is_live(index)).map(|index| (index, self.get(index)))).finish()
+    }
+}
+
+impl<T> Index<usize> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("dangling arena index")
+    }
+}
+
+impl<T> IndexMut<usize> for Arena<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("dangling arena index")
+    }
+}
@@ -0,0 +1,312 @@
+//! Construction of a trie from sorted keys in a single bottom-up pass.
+//!
+//! Sorted leaves are linked as they come, and every level is then built from the one below it by
+//! grouping the consecutive nodes sharing a parent. The leftmost and rightmost leaves of every
+//! subtree are carried up along with it, so the descendant ptrs are set without walking down.
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::iter::FromIterator;
+
+use crate::{Node, TrieNode, Xfast, XfastKey};
+
+/// Error returned by [`Xfast::try_from_sorted_iter`] for input a trie can't be built from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FromSortedError<K> {
+    /// The key is smaller than the key before it
+    Unsorted(K),
+    /// The key is equal to the key before it
+    Duplicate(K),
+    /// The key is larger than the range of the trie
+    OutOfRange(K),
+}
+
+impl<K: fmt::Debug> fmt::Display for FromSortedError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FromSortedError::Unsorted(key) => write!(f, "key {:?} is smaller than the key before it", key),
+            FromSortedError::Duplicate(key) => write!(f, "key {:?} appears more than once", key),
+            FromSortedError::OutOfRange(key) => write!(f, "key {:?} is out of the range of the trie", key),
+        }
+    }
+}
+
+impl<K: fmt::Debug> Error for FromSortedError<K> {}
+
+// node built at some level along with the extreme leaves of its subtree
+struct Subtree {
+    prefix: u128,
+    node: Node,
+    leftmost: Node,
+    rightmost: Node,
+}
+
+impl<K: XfastKey, V> Xfast<K, V> {
+
+    /// Creates a trie storing keys from zero up to `range` out of pairs sorted by strictly
+    /// increasing keys, in time linear in the size of the trie.
+    ///
+    /// The order of the keys isn't checked in release builds: unsorted or repeated keys leave a
+    /// trie giving unspecified results. [`try_from_sorted_iter`](Xfast::try_from_sorted_iter)
+    /// rejects such input instead.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let test_trie: Xfast<u32, u32> = Xfast::from_sorted_iter(1023, (0..1000).map(|key| (key, key * 2)));
+    ///     assert_eq!(test_trie.len(), 1000);
+    ///     assert_eq!(test_trie.successor(1000), None);
+    ///     assert_eq!(test_trie.predecessor(1000), Some((999, &1998)));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if a key is larger than `range`.
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(range: K, iter: I) -> Self {
+        Self::from_sorted_iter_with_hasher(range, iter, RandomState::new())
+    }
+
+    /// Creates a trie storing keys from zero up to `range` out of pairs sorted by strictly
+    /// increasing keys. Returns an error naming the first key which is out of order, repeated or
+    /// out of range.
+    /// # Examples
+    /// ```
+    ///     use xfast::{FromSortedError, Xfast};
+    ///
+    ///     let test_trie = Xfast::try_from_sorted_iter(31u8, vec![(1, "one"), (5, "five")]);
+    ///     assert_eq!(test_trie.map(|trie| trie.len()), Ok(2));
+    ///
+    ///     let test_trie = Xfast::try_from_sorted_iter(31u8, vec![(5, "five"), (1, "one")]);
+    ///     assert_eq!(test_trie.map(|trie| trie.len()), Err(FromSortedError::Unsorted(1)));
+    /// ```
+    pub fn try_from_sorted_iter<I: IntoIterator<Item = (K, V)>>(range: K, iter: I) -> Result<Self, FromSortedError<K>> {
+        Self::try_from_sorted_iter_with_hasher(range, iter, RandomState::new())
+    }
+}
+
+impl<K: XfastKey, V, S: BuildHasher + Clone> Xfast<K, V, S> {
+
+    /// Creates a trie storing keys from zero up to `range` out of pairs sorted by strictly
+    /// increasing keys, hashing the prefixes at every level with `hash_builder`. Works as
+    /// [`from_sorted_iter`](Xfast::from_sorted_iter) otherwise.
+    /// # Examples
+    /// ```
+    ///     use xfast::{BuildIntHasher, Xfast};
+    ///
+    ///     let pairs = (0..1000).map(|key| (key, key * 2));
+    ///     let test_trie: Xfast<u32, u32, _> = Xfast::from_sorted_iter_with_hasher(1023, pairs, BuildIntHasher::with_seed(7));
+    ///     assert_eq!(test_trie.predecessor(1000), Some((999, &1998)));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if a key is larger than `range`.
+    pub fn from_sorted_iter_with_hasher<I: IntoIterator<Item = (K, V)>>(range: K, iter: I, hash_builder: S) -> Self {
+        match Self::with_hasher(range, hash_builder).build_sorted(iter, false) {
+            Ok(trie) => trie,
+            Err(_) => unreachable!("only checked builds reject keys"),
+        }
+    }
+
+    /// Creates a trie storing keys from zero up to `range` out of pairs sorted by strictly
+    /// increasing keys, hashing the prefixes at every level with `hash_builder`. Works as
+    /// [`try_from_sorted_iter`](Xfast::try_from_sorted_iter) otherwise.
+    /// # Examples
+    /// ```
+    ///     use xfast::{BuildIntHasher, FromSortedError, Xfast};
+    ///
+    ///     let test_trie = Xfast::try_from_sorted_iter_with_hasher(31u8, vec![(5, "five"), (5, "v")], BuildIntHasher::default());
+    ///     assert_eq!(test_trie.map(|trie| trie.len()), Err(FromSortedError::Duplicate(5)));
+    /// ```
+    pub fn try_from_sorted_iter_with_hasher<I: IntoIterator<Item = (K, V)>>(range: K, iter: I, hash_builder: S) -> Result<Self, FromSortedError<K>> {
+        Self::with_hasher(range, hash_builder).build_sorted(iter, true)
+    }
+}
+
+impl<K: XfastKey, V, S: BuildHasher> Xfast<K, V, S> {
+
+    // fill the empty trie with sorted pairs, checking the keys are strictly increasing if `checked`
+    pub(crate) fn build_sorted<I: IntoIterator<Item = (K, V)>>(mut self, iter: I, checked: bool) -> Result<Self, FromSortedError<K>> {
+        // link the leaves in the order of their keys
+        let mut level: Vec<Subtree> = Vec::new();
+        for (key, value) in iter {
+            let bits = key.to_bits();
+            if bits > self.max_key() {
+                if checked {
+                    return Err(FromSortedError::OutOfRange(key));
+                }
+                panic!("key out of the range of the trie");
+            }
+            let leaf = self.nodes.alloc(TrieNode::new(bits, self.nr_levels));
+            self.values.insert(leaf, value);
+            if let Some(previous) = level.last() {
+                let previous_key = self.nodes[previous.node].key;
+                if checked && bits <= previous_key {
+                    let error = if bits == previous_key { FromSortedError::Duplicate(key) } else { FromSortedError::Unsorted(key) };
+                    return Err(error);
+                }
+                debug_assert!(bits > previous_key, "keys must be strictly increasing");
+                self.nodes[previous.node].right = Some(leaf);
+                self.nodes[leaf].left = Some(previous.node);
+            }
+            self.levels.insert(self.nr_levels, bits, leaf);
+            level.push(Subtree { prefix: bits, node: leaf, leftmost: leaf, rightmost: leaf });
+        }
+        let (first, last) = match (level.first(), level.last()) {
+            (Some(first), Some(last)) => (first.node, last.node),
+            _ => return Ok(self),
+        };
+        self.min_leaf = Some(first);
+        self.max_leaf = Some(last);
+
+        // build every level from the one below it, up to the root
+        for depth in (0..self.nr_levels).rev() {
+            let mut parents: Vec<Subtree> = Vec::new();
+            for child in level {
+                let prefix = child.prefix >> 1;
+                match parents.last_mut() {
+                    Some(parent) if parent.prefix == prefix => {
+                        self.link_child(parent.node, child.prefix & 1, child.node);
+                        parent.rightmost = child.rightmost;
+                    }
+                    _ => {
+                        let node = if depth == 0 {
+                            self.levels.node(0, 0)
+                        }
+                        else {
+                            let node = self.nodes.alloc(TrieNode::new_internal(depth));
+                            self.levels.insert(depth, prefix, node);
+                            node
+                        };
+                        self.link_child(node, child.prefix & 1, child.node);
+                        parents.push(Subtree { prefix, node, leftmost: child.leftmost, rightmost: child.rightmost });
+                    }
+                }
+            }
+
+            // a missing child is replaced by a descendant ptr to the nearest leaf of the other one
+            for parent in parents.iter() {
+                let node = &mut self.nodes[parent.node];
+                if node.is_desc_left {
+                    node.left = Some(parent.leftmost);
+                }
+                if node.is_desc_right {
+                    node.right = Some(parent.rightmost);
+                }
+            }
+            level = parents;
+        }
+        Ok(self)
+    }
+}
+
+impl<K: XfastKey, V, S: BuildHasher + Clone + Default> FromIterator<(K, V)> for Xfast<K, V, S> {
+    /// Creates a trie spanning the full width of the key type out of pairs in any order. The pairs
+    /// are sorted before the trie is built, and the last value given for a key is kept.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut pairs: Vec<(K, V)> = iter.into_iter().collect();
+        // the stable sort keeps the pairs of a key in the order they were given
+        pairs.sort_by_key(|pair| pair.0);
+        let mut sorted: Vec<(K, V)> = Vec::with_capacity(pairs.len());
+        for pair in pairs {
+            match sorted.last_mut() {
+                Some(last) if last.0 == pair.0 => *last = pair,
+                _ => sorted.push(pair),
+            }
+        }
+        match Self::default().build_sorted(sorted, false) {
+            Ok(trie) => trie,
+            Err(_) => unreachable!("only checked builds reject keys"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BuildIntHasher, FromSortedError, Xfast};
+
+    const KEYS: [u16; 8] = [0, 1, 5, 11, 18, 40, 41, 63];
+
+    // prefix of a node along with the keys its left and right ptrs lead to
+    type NodeLayout = (u128, Option<u128>, Option<u128>);
+
+    // nodes of every level of the trie
+    fn layout(test_trie: &Xfast<u16, u16>) -> Vec<Vec<NodeLayout>> {
+        let entries = test_trie.levels.entries();
+        (0..=test_trie.nr_levels).map(|level| {
+            let mut nodes: Vec<_> = entries.iter().filter(|entry| entry.0 == level).map(|&(_, prefix, node)| {
+                let node = &test_trie.nodes[node];
+                let key = |side: Option<usize>| side.map(|side| test_trie.nodes[side].key);
+                (prefix, key(node.left), key(node.right))
+            }).collect();
+            nodes.sort();
+            nodes
+        }).collect()
+    }
+
+    #[test]
+    fn same_as_inserted() -> Result<(), String> {
+        let built = Xfast::from_sorted_iter(63, KEYS.iter().map(|&key| (key, key)));
+        let mut inserted = Xfast::new(63);
+        for &key in KEYS.iter().rev() {
+            inserted.insert(key, key);
+        }
+        if layout(&built) == layout(&inserted) && built.first_key_value() == Some((0, &0)) && built.last_key_value() == Some((63, &63)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Bulk built trie should match the one built by insertions"))
+        }
+    }
+
+    #[test]
+    fn built_with_hasher() -> Result<(), String> {
+        let built = Xfast::from_sorted_iter_with_hasher(63, KEYS.iter().map(|&key| (key, key)), BuildIntHasher::with_seed(5));
+        let checked = Xfast::try_from_sorted_iter_with_hasher(63, KEYS.iter().map(|&key| (key, key)), BuildIntHasher::with_seed(5));
+        let expected = Xfast::from_sorted_iter(63, KEYS.iter().map(|&key| (key, key)));
+        let same = |test_trie: &Xfast<u16, u16, BuildIntHasher>| (0..64).all(|key| test_trie.successor(key) == expected.successor(key))
+            && test_trie.iter().eq(expected.iter());
+        if same(&built) && checked.as_ref().is_ok_and(same) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Tries built with another hasher should hold the same keys"))
+        }
+    }
+
+    #[test]
+    fn rejected_input() -> Result<(), String> {
+        let errors: Vec<Option<FromSortedError<u16>>> = vec![
+            Xfast::try_from_sorted_iter(63, vec![(1, ()), (5, ()), (3, ())]).err(),
+            Xfast::try_from_sorted_iter(63, vec![(1, ()), (5, ()), (5, ())]).err(),
+            Xfast::try_from_sorted_iter(63, vec![(1, ()), (64, ())]).err(),
+            Xfast::try_from_sorted_iter(63, vec![(1, ()), (63, ())]).err(),
+        ];
+        let expected = vec![
+            Some(FromSortedError::Unsorted(3)),
+            Some(FromSortedError::Duplicate(5)),
+            Some(FromSortedError::OutOfRange(64)),
+            None,
+        ];
+        if errors == expected {
+            Ok(())
+        }
+        else {
+            Err(format!("Bad input should be rejected, got {:?}", errors))
+        }
+    }
+
+    #[test]
+    fn collect_unsorted() -> Result<(), String> {
+        let mut test_trie: Xfast<u16, u16> = vec![(40, 1), (5, 1), (40, 2), (1, 1)].into_iter().collect();
+        test_trie.insert(3, 1);
+        test_trie.remove(5);
+        let pairs: Vec<(u16, &u16)> = test_trie.iter().collect();
+        if pairs == vec![(1, &1), (3, &1), (40, &2)] && test_trie.successor(4) == Some((40, &2)) {
+            Ok(())
+        }
+        else {
+            Err(format!("Collected trie is wrong, got {:?}", pairs))
+        }
+    }
+}
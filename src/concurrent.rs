@@ -0,0 +1,276 @@
+//! A trie shared between a single writer and any number of readers which never take a lock.
+//!
+//! Two copies of the trie are kept in the left-right scheme. Readers announce themselves on a read
+//! indicator and query whichever copy is published, while the writer updates the other copy,
+//! publishes it and waits for the readers still on the old copy to leave before replaying the
+//! update there. A copy is thus never modified while a reader is on it, so readers can't see a
+//! half-updated descendant ptr or leaf link.
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{Xfast, XfastKey};
+
+/// A bitwise trie which can be read from many threads while one thread writes to it.
+///
+/// Reads run on a published copy of the trie without taking locks or waiting on the writer.
+/// Writes are serialized and applied to both copies in turn, so they cost about twice as much as
+/// on an [`Xfast`] and wait for the readers of the previously published copy to finish. Values
+/// are handed out as clones since the copy they came from may be updated once the read is over.
+/// # Examples
+/// ```
+///     use std::sync::Arc;
+///     use std::thread;
+///     use xfast::ConcurrentXfast;
+///
+///     let test_trie: Arc<ConcurrentXfast<u32, u32>> = Arc::new(ConcurrentXfast::new(1023));
+///     let writer = {
+///         let test_trie = Arc::clone(&test_trie);
+///         thread::spawn(move || {
+///             for key in (0..1024).step_by(8) {
+///                 test_trie.insert(key, key * 2);
+///             }
+///         })
+///     };
+///     // readers only ever see multiples of 8
+///     if let Some((key, value)) = test_trie.successor(100) {
+///         assert!(key % 8 == 0 && value == key * 2);
+///     }
+///     writer.join().unwrap();
+///     assert_eq!(test_trie.successor(100), Some((104, 208)));
+/// ```
+pub struct ConcurrentXfast<K, V> {
+    // the two copies of the trie
+    tries: [UnsafeCell<Xfast<K, V>>; 2],
+    // index of the copy readers are sent to
+    published: AtomicUsize,
+    // count of readers announced on either read indicator, and which indicator new readers use
+    readers: [AtomicUsize; 2],
+    indicator: AtomicUsize,
+    // held by the writer for the whole update of both copies
+    writer: Mutex<()>,
+}
+
+// readers only get shared references to a copy nobody writes to, and the writer is serialized
+unsafe impl<K: Send + Sync, V: Send + Sync> Sync for ConcurrentXfast<K, V> {}
+
+// leaves the read indicator when the read is over, even if it panicked
+struct ReadGuard<'a>(&'a AtomicUsize);
+
+impl<'a> Drop for ReadGuard<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<K: XfastKey, V: Clone> ConcurrentXfast<K, V> {
+
+    /// Creates a new concurrent trie to store keys from zero up to `range`
+    pub fn new(range: K) -> Self {
+        Self::with_copies(Xfast::new(range), Xfast::new(range))
+    }
+
+    fn with_copies(left: Xfast<K, V>, right: Xfast<K, V>) -> Self {
+        ConcurrentXfast {
+            tries: [UnsafeCell::new(left), UnsafeCell::new(right)],
+            published: AtomicUsize::new(0),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            indicator: AtomicUsize::new(0),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Runs `read` on the published copy of the trie and returns its result. The copy doesn't
+    /// change while `read` runs, so it sees the trie as it was after some complete write.
+    /// # Examples
+    /// ```
+    ///     use xfast::ConcurrentXfast;
+    ///
+    ///     let test_trie: ConcurrentXfast<u32, u32> = ConcurrentXfast::new(1023);
+    ///     test_trie.insert(11, 1);
+    ///     test_trie.insert(5, 1);
+    ///     let keys: Vec<u32> = test_trie.read(|trie| trie.iter().map(|(key, _)| key).collect());
+    ///     assert_eq!(keys, vec![5, 11]);
+    /// ```
+    pub fn read<R, F: FnOnce(&Xfast<K, V>) -> R>(&self, read: F) -> R {
+        let indicator = &self.readers[self.indicator.load(Ordering::SeqCst)];
+        indicator.fetch_add(1, Ordering::SeqCst);
+        let _guard = ReadGuard(indicator);
+        let published = self.published.load(Ordering::SeqCst);
+        // the writer leaves a published copy alone until every reader announced on it is gone
+        read(unsafe { &*self.tries[published].get() })
+    }
+
+    /// Returns the count of values stored in the trie
+    pub fn len(&self) -> usize {
+        self.read(|trie| trie.len())
+    }
+
+    /// Returns true if the trie stores no values
+    pub fn is_empty(&self) -> bool {
+        self.read(|trie| trie.is_empty())
+    }
+
+    /// Returns a clone of the value stored with `key`
+    pub fn get(&self, key: K) -> Option<V> {
+        self.read(|trie| trie.get(key).cloned())
+    }
+
+    /// Returns true if the trie stores a value for `key`
+    pub fn contains_key(&self, key: K) -> bool {
+        self.read(|trie| trie.contains_key(key))
+    }
+
+    /// Returns the smallest key more than or equal to `key` along with a clone of its value
+    pub fn successor(&self, key: K) -> Option<(K, V)> {
+        self.read(|trie| trie.successor(key).map(|(found, value)| (found, value.clone())))
+    }
+
+    /// Returns the largest key less than or equal to `key` along with a clone of its value
+    pub fn predecessor(&self, key: K) -> Option<(K, V)> {
+        self.read(|trie| trie.predecessor(key).map(|(found, value)| (found, value.clone())))
+    }
+
+    /// Returns the smallest key of the trie along with a clone of its value
+    pub fn first_key_value(&self) -> Option<(K, V)> {
+        self.read(|trie| trie.first_key_value().map(|(key, value)| (key, value.clone())))
+    }
+
+    /// Returns the largest key of the trie along with a clone of its value
+    pub fn last_key_value(&self) -> Option<(K, V)> {
+        self.read(|trie| trie.last_key_value().map(|(key, value)| (key, value.clone())))
+    }
+
+    // apply `write` to the copy readers can't see, publish it and replay `write` on the other copy
+    // once its readers are gone; `write` has to leave both copies the same
+    fn write<R, F: Fn(&mut Xfast<K, V>) -> R>(&self, write: F) -> R {
+        let _writer = self.writer.lock().expect("a writer panicked while updating the trie");
+        let published = self.published.load(Ordering::SeqCst);
+        let standby = 1 - published;
+        let result = write(unsafe { &mut *self.tries[standby].get() });
+        self.published.store(standby, Ordering::SeqCst);
+        self.wait_for_readers();
+        write(unsafe { &mut *self.tries[published].get() });
+        result
+    }
+
+    // wait for every reader which may still be on the previously published copy
+    fn wait_for_readers(&self) {
+        let current = self.indicator.load(Ordering::SeqCst);
+        let next = 1 - current;
+        // readers left over on the next indicator are from before the previous write
+        while self.readers[next].load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        self.indicator.store(next, Ordering::SeqCst);
+        while self.readers[current].load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+    }
+
+    /// Insert `key` and `value` into the trie. If the key was already present its value is replaced
+    /// and the previous value is returned, otherwise it returns None.
+    ///
+    /// # Panics
+    /// Panics if `key` is larger than the range the trie was created with.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        // checked up front so that a panic can't leave the copies apart
+        assert!(key.to_bits() <= self.read(|trie| trie.max_key()), "key out of the range of the trie");
+        self.write(|trie| trie.insert(key, value.clone()))
+    }
+
+    /// Delete a key from the trie. If the key doesn't exist it returns None else returns the value
+    /// that was stored with it.
+    pub fn remove(&self, key: K) -> Option<V> {
+        self.write(|trie| trie.remove(key))
+    }
+}
+
+impl<K: XfastKey, V: Clone> Default for ConcurrentXfast<K, V> {
+    /// Creates an empty trie spanning the full width of the key type
+    fn default() -> Self {
+        Self::with_copies(Xfast::default(), Xfast::default())
+    }
+}
+
+impl<K: XfastKey + fmt::Debug, V: Clone + fmt::Debug> fmt::Debug for ConcurrentXfast<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.read(|trie| f.debug_map().entries(trie.iter()).finish())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::ConcurrentXfast;
+
+    #[test]
+    fn readers_see_whole_writes() -> Result<(), String> {
+        let test_trie: Arc<ConcurrentXfast<u32, u32>> = Arc::new(ConcurrentXfast::new(1023));
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..2).map(|reader| {
+            let test_trie = Arc::clone(&test_trie);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut query = reader;
+                let mut bad = Vec::new();
+                while !done.load(Ordering::SeqCst) {
+                    query = (query * 31 + 7) % 1024;
+                    // every key is a multiple of 3 stored with its double
+                    if let Some((key, value)) = test_trie.successor(query) {
+                        if key < query || key % 3 != 0 || value != key * 2 {
+                            bad.push((query, key));
+                        }
+                    }
+                    // now and then, a copy holds the keys in order with both ends linked up
+                    let linked = query % 16 != 0 || test_trie.read(|trie| {
+                        trie.iter().zip(trie.iter().skip(1)).all(|((left, _), (right, _))| left < right)
+                            && trie.iter().count() == trie.len() && trie.iter().rev().count() == trie.len()
+                    });
+                    if !linked {
+                        bad.push((query, u32::MAX));
+                    }
+                }
+                bad
+            })
+        }).collect();
+
+        for key in (0..1024).filter(|key| key % 3 == 0) {
+            test_trie.insert(key, key * 2);
+        }
+        for key in (0..1024).filter(|key| key % 6 == 0) {
+            test_trie.remove(key);
+        }
+        done.store(true, Ordering::SeqCst);
+        let bad: Vec<(u32, u32)> = readers.into_iter().flat_map(|reader| reader.join().expect("reader panicked")).collect();
+        if bad.is_empty() && test_trie.len() == 171 && test_trie.predecessor(1023) == Some((1023, 2046)) {
+            Ok(())
+        }
+        else {
+            Err(format!("Readers saw a broken trie, got {:?}", &bad[..bad.len().min(10)]))
+        }
+    }
+
+    #[test]
+    fn both_copies_updated() -> Result<(), String> {
+        let test_trie: ConcurrentXfast<u8, String> = ConcurrentXfast::new(255);
+        let mut previous = Vec::new();
+        for round in 0..4 {
+            previous.push(test_trie.insert(7, round.to_string()));
+        }
+        let removed = test_trie.remove(7);
+        if previous == vec![None, Some(String::from("0")), Some(String::from("1")), Some(String::from("2"))]
+            && removed == Some(String::from("3")) && test_trie.is_empty() && test_trie.remove(7).is_none() {
+            Ok(())
+        }
+        else {
+            Err(format!("Updates should reach both copies, got {:?}", previous))
+        }
+    }
+}
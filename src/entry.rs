@@ -0,0 +1,235 @@
+//! Entries of the trie for in-place insert-or-update.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use crate::{Node, Xfast, XfastKey};
+
+/// A view into a single key of a `Xfast` trie, which is either present or absent.
+///
+/// Constructed by [`Xfast::entry`]. Looking up the entry only probes the leaf level of the trie,
+/// so updating an existing key doesn't search for its predecessor and successor.
+pub enum Entry<'a, K, V, S = RandomState> {
+    /// The key is present in the trie
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    /// The key is absent from the trie
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// A view into a key present in a `Xfast` trie
+pub struct OccupiedEntry<'a, K, V, S = RandomState> {
+    trie: &'a mut Xfast<K, V, S>,
+    // leaf holding the key
+    node: Node,
+}
+
+/// A view into a key absent from a `Xfast` trie
+pub struct VacantEntry<'a, K, V, S = RandomState> {
+    trie: &'a mut Xfast<K, V, S>,
+    key: K,
+}
+
+impl<'a, K: XfastKey, V, S: BuildHasher> Entry<'a, K, V, S> {
+
+    /// Returns the key of the entry
+    pub fn key(&self) -> K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `default` if the key is absent and returns a mutable reference to the value.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.entry(11).or_insert("eleven");
+    ///     assert_eq!(*test_trie.entry(11).or_insert("xi"), "eleven");
+    /// ```
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Inserts the result of `default` if the key is absent and returns a mutable reference to the value.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, String> = Xfast::new(31);
+    ///     test_trie.entry(11).or_insert_with(|| String::from("eleven"));
+    ///     assert_eq!(test_trie.get(11).map(String::as_str), Some("eleven"));
+    /// ```
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Calls `f` on the value if the key is present.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, u32> = Xfast::new(31);
+    ///     test_trie.entry(11).and_modify(|count| *count += 1).or_insert(1);
+    ///     test_trie.entry(11).and_modify(|count| *count += 1).or_insert(1);
+    ///     assert_eq!(test_trie.get(11), Some(&2));
+    /// ```
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V: Default, S: BuildHasher> Entry<'a, K, V, S> {
+
+    /// Inserts the default value if the key is absent and returns a mutable reference to the value.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, Vec<&str>> = Xfast::new(31);
+    ///     test_trie.entry(11).or_default().push("eleven");
+    ///     assert_eq!(test_trie.get(11), Some(&vec!["eleven"]));
+    /// ```
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: XfastKey, V, S: BuildHasher> OccupiedEntry<'a, K, V, S> {
+
+    pub(crate) fn new(trie: &'a mut Xfast<K, V, S>, node: Node) -> Self {
+        OccupiedEntry { trie, node }
+    }
+
+    /// Returns the key of the entry
+    pub fn key(&self) -> K {
+        self.trie.leaf_key(self.node)
+    }
+
+    /// Returns a reference to the value of the entry
+    pub fn get(&self) -> &V {
+        self.trie.leaf_value(self.node)
+    }
+
+    /// Returns a mutable reference to the value of the entry
+    pub fn get_mut(&mut self) -> &mut V {
+        self.trie.leaf_value_mut(self.node)
+    }
+
+    /// Converts the entry into a mutable reference to its value
+    pub fn into_mut(self) -> &'a mut V {
+        self.trie.leaf_value_mut(self.node)
+    }
+
+    /// Replaces the value of the entry and returns the old one
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// Removes the entry from the trie and returns its value
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry from the trie and returns its key and value.
+    /// # Examples
+    /// ```
+    ///     use xfast::{Entry, Xfast};
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     if let Entry::Occupied(entry) = test_trie.entry(11) {
+    ///         assert_eq!(entry.remove_entry(), (11, "eleven"));
+    ///     }
+    ///     assert!(test_trie.is_empty());
+    /// ```
+    pub fn remove_entry(self) -> (K, V) {
+        let key = self.key();
+        (key, self.trie.remove_leaf(self.node))
+    }
+}
+
+impl<'a, K: XfastKey, V, S: BuildHasher> VacantEntry<'a, K, V, S> {
+
+    pub(crate) fn new(trie: &'a mut Xfast<K, V, S>, key: K) -> Self {
+        VacantEntry { trie, key }
+    }
+
+    /// Returns the key of the entry
+    pub fn key(&self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` for the key of the entry and returns a mutable reference to it.
+    /// # Panics
+    /// Panics if the key is larger than the range the trie was created with.
+    pub fn insert(self, value: V) -> &'a mut V {
+        let node = self.trie.insert_leaf(self.key.to_bits(), value);
+        self.trie.leaf_value_mut(node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Entry, Xfast};
+
+    fn init() -> Xfast<usize, u32> {
+        let mut test_trie = Xfast::new(31);
+        for &key in [11, 1, 18, 5].iter() {
+            test_trie.insert(key, 0);
+        }
+        test_trie
+    }
+
+    #[test]
+    fn counters() -> Result<(), String> {
+        let mut test_trie = init();
+        for &key in [5, 7, 5, 31, 7, 5].iter() {
+            test_trie.entry(key).and_modify(|count| *count += 1).or_insert(1);
+        }
+        let counts: Vec<Option<&u32>> = [5, 7, 31, 11].iter().map(|&key| test_trie.get(key)).collect();
+        if counts == vec![Some(&3), Some(&2), Some(&1), Some(&0)] && test_trie.successor(19) == Some((31, &1)) {
+            Ok(())
+        }
+        else {
+            Err(format!("Entries should count every update, got {:?}", counts))
+        }
+    }
+
+    #[test]
+    fn remove_entry() -> Result<(), String> {
+        let mut test_trie = init();
+        let removed = match test_trie.entry(11) {
+            Entry::Occupied(entry) => Some(entry.remove_entry()),
+            Entry::Vacant(_) => None,
+        };
+        if removed == Some((11, 0)) && test_trie.successor(6) == Some((18, &0)) && test_trie.predecessor(17) == Some((5, &0)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Removing the entry should unlink its leaf"))
+        }
+    }
+
+    #[test]
+    fn vacant_key() -> Result<(), String> {
+        let mut test_trie = init();
+        match test_trie.entry(3) {
+            Entry::Vacant(entry) if entry.key() == 3 => Ok(()),
+            _ => Err(String::from("Key 3 should be vacant")),
+        }
+    }
+}
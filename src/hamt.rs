@@ -0,0 +1,222 @@
+//! Persistent hash array mapped trie from `u128` keys to values.
+//!
+//! Every node branches 32 ways on 5 bits of the hash of the key and only stores the slots in
+//! use, indexed through a bitmap. Nodes are shared through `Arc`s, so cloning a map is O(1) and an
+//! update only copies the nodes on the path to the updated slot. The hash is a bijection of the
+//! key, so two keys never share a full hash and no collision lists are needed.
+
+use std::mem;
+use std::sync::Arc;
+
+const BITS_PER_LEVEL: u32 = 5;
+
+// bijective mix of the key bits, spreading neighbouring keys across the branches
+fn hash(key: u128) -> u128 {
+    let mut hash = key ^ (key >> 64);
+    hash = hash.wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835);
+    hash ^= hash >> 61;
+    hash = hash.wrapping_mul(0xbf58_476d_1ce4_e5b9_94d0_49bb_1331_11eb);
+    hash ^ (hash >> 67)
+}
+
+// bit of the slot at `depth` for `hash` within the bitmap of a node
+fn slot_bit(hash: u128, depth: u32) -> u32 {
+    1 << ((hash >> (BITS_PER_LEVEL * depth)) & 31) as u32
+}
+
+#[derive(Debug, Clone)]
+enum Slot<T> {
+    Entry(u128, T),
+    Branch(Arc<HamtNode<T>>),
+}
+
+#[derive(Debug, Clone)]
+struct HamtNode<T> {
+    bitmap: u32,
+    // slots in the order of their bits
+    slots: Vec<Slot<T>>,
+}
+
+impl<T> HamtNode<T> {
+    fn empty() -> Self {
+        HamtNode { bitmap: 0, slots: Vec::new() }
+    }
+
+    // position in `slots` of the slot for `bit`
+    fn index(&self, bit: u32) -> usize {
+        (self.bitmap & (bit - 1)).count_ones() as usize
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Hamt<T> {
+    root: Arc<HamtNode<T>>,
+    len: usize,
+}
+
+impl<T> Clone for Hamt<T> {
+    // the copy shares every node with the map
+    fn clone(&self) -> Self {
+        Hamt { root: Arc::clone(&self.root), len: self.len }
+    }
+}
+
+impl<T: Clone> Hamt<T> {
+    pub(crate) fn new() -> Self {
+        Hamt { root: Arc::new(HamtNode::empty()), len: 0 }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, key: u128) -> Option<&T> {
+        let hash = hash(key);
+        let mut node = &self.root;
+        let mut depth = 0;
+        loop {
+            let bit = slot_bit(hash, depth);
+            if node.bitmap & bit == 0 {
+                return None;
+            }
+            match &node.slots[node.index(bit)] {
+                Slot::Entry(found, value) => return if *found == key { Some(value) } else { None },
+                Slot::Branch(child) => node = child,
+            }
+            depth += 1;
+        }
+    }
+
+    pub(crate) fn contains_key(&self, key: u128) -> bool {
+        self.get(key).is_some()
+    }
+
+    // mutable reference to the value of `key`, copying the shared nodes on its path
+    pub(crate) fn get_mut(&mut self, key: u128) -> Option<&mut T> {
+        let hash = hash(key);
+        let mut node = Arc::make_mut(&mut self.root);
+        let mut depth = 0;
+        loop {
+            let bit = slot_bit(hash, depth);
+            if node.bitmap & bit == 0 {
+                return None;
+            }
+            let index = node.index(bit);
+            match &mut node.slots[index] {
+                Slot::Entry(found, value) => return if *found == key { Some(value) } else { None },
+                Slot::Branch(child) => node = Arc::make_mut(child),
+            }
+            depth += 1;
+        }
+    }
+
+    // store `value` with `key`, returning the value it replaces
+    pub(crate) fn insert(&mut self, key: u128, value: T) -> Option<T> {
+        let previous = Self::insert_below(&mut self.root, hash(key), key, value, 0);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    fn insert_below(node: &mut Arc<HamtNode<T>>, key_hash: u128, key: u128, value: T, depth: u32) -> Option<T> {
+        let node = Arc::make_mut(node);
+        let bit = slot_bit(key_hash, depth);
+        let index = node.index(bit);
+        if node.bitmap & bit == 0 {
+            node.bitmap |= bit;
+            node.slots.insert(index, Slot::Entry(key, value));
+            return None;
+        }
+        match &mut node.slots[index] {
+            Slot::Branch(child) => return Self::insert_below(child, key_hash, key, value, depth + 1),
+            Slot::Entry(found, previous) if *found == key => return Some(mem::replace(previous, value)),
+            Slot::Entry(..) => {}
+        }
+        // another key takes the slot, so both move down into a new node
+        let other = mem::replace(&mut node.slots[index], Slot::Branch(Arc::new(HamtNode::empty())));
+        if let (Slot::Entry(other_key, other_value), Slot::Branch(child)) = (other, &mut node.slots[index]) {
+            Self::insert_below(child, hash(other_key), other_key, other_value, depth + 1);
+            Self::insert_below(child, key_hash, key, value, depth + 1);
+        }
+        None
+    }
+
+    // remove `key`, returning its value
+    pub(crate) fn remove(&mut self, key: u128) -> Option<T> {
+        let removed = Self::remove_below(&mut self.root, hash(key), key, 0);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    fn remove_below(node: &mut Arc<HamtNode<T>>, key_hash: u128, key: u128, depth: u32) -> Option<T> {
+        let bit = slot_bit(key_hash, depth);
+        if node.bitmap & bit == 0 {
+            return None;
+        }
+        let index = node.index(bit);
+        // look before copying the node, so that a missing key leaves it shared
+        if let Slot::Entry(found, _) = &node.slots[index] {
+            if *found != key {
+                return None;
+            }
+        }
+        let node = Arc::make_mut(node);
+        match &mut node.slots[index] {
+            Slot::Branch(child) => {
+                let removed = Self::remove_below(child, key_hash, key, depth + 1)?;
+                // a branch left with a single entry is replaced by the entry
+                let collapsed = match child.slots.as_slice() {
+                    [Slot::Entry(other_key, other_value)] => Some(Slot::Entry(*other_key, other_value.clone())),
+                    _ => None,
+                };
+                if let Some(slot) = collapsed {
+                    node.slots[index] = slot;
+                }
+                Some(removed)
+            }
+            Slot::Entry(..) => {
+                node.bitmap &= !bit;
+                match node.slots.remove(index) {
+                    Slot::Entry(_, value) => Some(value),
+                    Slot::Branch(_) => unreachable!("slot held an entry"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::Hamt;
+
+    #[test]
+    fn shared_versions() -> Result<(), String> {
+        let mut map: Hamt<u128> = Hamt::new();
+        let mut expected = HashMap::new();
+        for key in 0..5000u128 {
+            map.insert(key * 7919, key);
+            expected.insert(key * 7919, key);
+        }
+        let snapshot = map.clone();
+        for key in (0..5000u128).filter(|key| key % 3 != 0) {
+            map.remove(key * 7919);
+            expected.remove(&(key * 7919));
+        }
+        if let Some(value) = map.get_mut(0) {
+            *value = 1;
+        }
+        let same = (0..5000u128).all(|key| map.get(key * 7919).copied() == expected.get(&(key * 7919)).map(|&value| if key == 0 { 1 } else { value }));
+        let untouched = (0..5000u128).all(|key| snapshot.get(key * 7919) == Some(&key));
+        if same && untouched && map.len() == 1667 && snapshot.len() == 5000 && map.get(1).is_none() {
+            Ok(())
+        }
+        else {
+            Err(String::from("Map versions don't hold their own entries"))
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Fast hashing of the integer prefixes stored in the level maps.
+//!
+//! Every lookup in the trie probes the level maps with integer prefixes, and the default SipHash
+//! spends most of a probe on its rounds. [`IntHasher`] hashes every word with a single 64 by 64 bit
+//! multiplication, folding the high half of the product onto the low half so that both the bucket
+//! index and the tag bits of the map depend on every bit of the key. It isn't resistant to keys
+//! chosen to collide, so it should only be used when the keys don't come from an adversary.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+// odd multiplier with well spread bits, from the fractional part of the golden ratio
+const MULTIPLIER: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// product of `word` and the multiplier with its high half folded onto its low half
+fn fold_multiply(word: u64) -> u64 {
+    let product = (word as u128) * (MULTIPLIER as u128);
+    (product as u64) ^ ((product >> 64) as u64)
+}
+
+/// A multiply-shift hasher for integer keys, built by [`BuildIntHasher`].
+#[derive(Debug, Clone)]
+pub struct IntHasher {
+    hash: u64,
+}
+
+impl IntHasher {
+    fn mix(&mut self, word: u64) {
+        self.hash = fold_multiply(self.hash ^ word);
+    }
+}
+
+impl Hasher for IntHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0u8; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.mix(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, value: u8) {
+        self.mix(value as u64);
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.mix(value as u64);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.mix(value as u64);
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.mix(value);
+    }
+
+    fn write_u128(&mut self, value: u128) {
+        self.mix(value as u64);
+        self.mix((value >> 64) as u64);
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.mix(value as u64);
+    }
+}
+
+/// Builds [`IntHasher`]s for the level maps of a trie.
+///
+/// A default builder draws a random seed, while [`with_seed`](BuildIntHasher::with_seed) gives a
+/// deterministic one, which hashes the same keys to the same buckets on every run and makes the
+/// timings of tests and benchmarks reproducible.
+/// # Examples
+/// ```
+///     use std::hash::BuildHasher;
+///     use xfast::{BuildIntHasher, Xfast};
+///
+///     let deterministic = BuildIntHasher::with_seed(42);
+///     assert_eq!(deterministic.hash_one(7u128), BuildIntHasher::with_seed(42).hash_one(7u128));
+///
+///     let mut test_trie: Xfast<u64, &str, _> = Xfast::with_hasher(1023, deterministic);
+///     test_trie.insert(11, "eleven");
+///     assert_eq!(test_trie.predecessor(20), Some((11, &"eleven")));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BuildIntHasher {
+    seed: u64,
+}
+
+impl BuildIntHasher {
+    /// Creates a builder whose hashers always start from `seed`
+    pub const fn with_seed(seed: u64) -> Self {
+        BuildIntHasher { seed }
+    }
+}
+
+impl Default for BuildIntHasher {
+    /// Creates a builder with a random seed
+    fn default() -> Self {
+        BuildIntHasher { seed: RandomState::new().build_hasher().finish() }
+    }
+}
+
+impl BuildHasher for BuildIntHasher {
+    type Hasher = IntHasher;
+
+    fn build_hasher(&self) -> IntHasher {
+        IntHasher { hash: self.seed }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::hash::BuildHasher;
+
+    use super::BuildIntHasher;
+    use crate::Xfast;
+
+    #[test]
+    fn same_trie_with_any_hasher() -> Result<(), String> {
+        let keys: Vec<u64> = (0..2000u64).map(|key| key.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 3).collect();
+        let mut default_trie: Xfast<u64, u64> = Xfast::default();
+        let mut seeded: Xfast<u64, u64, _> = Xfast::with_capacity_and_hasher(u64::MAX, 1000, BuildIntHasher::with_seed(3));
+        let mut random: Xfast<u64, u64, BuildIntHasher> = Xfast::default();
+        for (index, &key) in keys.iter().enumerate() {
+            default_trie.insert(key, index as u64);
+            seeded.insert(key, index as u64);
+            random.insert(key, index as u64);
+        }
+        for &key in keys.iter().step_by(3) {
+            default_trie.remove(key);
+            seeded.remove(key);
+            random.remove(key);
+        }
+        let queries = (0..500u64).map(|query| query.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        let same = queries.clone().all(|query| {
+            default_trie.successor(query) == seeded.successor(query) && default_trie.predecessor(query) == random.predecessor(query)
+        });
+        if same && default_trie.iter().eq(seeded.iter()) && default_trie.iter().eq(random.iter()) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Tries with other hashers should hold the same keys"))
+        }
+    }
+
+    #[test]
+    fn seeded_hashes() -> Result<(), String> {
+        let seeded = BuildIntHasher::with_seed(11);
+        let repeated = (0..100u128).all(|key| seeded.hash_one(key) == BuildIntHasher::with_seed(11).hash_one(key));
+        let reseeded = (0..100u128).filter(|&key| seeded.hash_one(key) == BuildIntHasher::with_seed(12).hash_one(key)).count();
+        // prefixes differing in a single bit, high or low, land in distinct low bits
+        let buckets: HashSet<u64> = (0..128).map(|bit| seeded.hash_one(1u128 << bit) & 0xfff).collect();
+        if repeated && reseeded == 0 && buckets.len() > 120 {
+            Ok(())
+        }
+        else {
+            Err(format!("Seeded hashes should be repeatable and spread, got {} buckets", buckets.len()))
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Integer types that can be stored as keys of the trie.
+
+/// An integer usable as a key of the trie.
+///
+/// Keys are stored through an order preserving encoding as an unsigned integer of `BITS` bits:
+/// unsigned keys are stored as they are while signed keys get their sign bit flipped, so that
+/// negative keys come before the non-negative ones.
+///
+/// # Examples
+/// ```
+///     use xfast::XfastKey;
+///
+///     assert!((-1i8).to_bits() < 0i8.to_bits());
+///     assert_eq!(i8::from_bits((-1i8).to_bits()), -1);
+///     assert_eq!(<u16 as XfastKey>::BITS, 16);
+/// ```
+pub trait XfastKey: Copy + Ord {
+    /// Width of the key in bits
+    const BITS: usize;
+
+    /// Returns the order preserving unsigned encoding of the key
+    fn to_bits(self) -> u128;
+
+    /// Returns the key encoded as `bits`
+    fn from_bits(bits: u128) -> Self;
+}
+
+macro_rules! unsigned_key {
+    ($($key:ty),*) => {
+        $(
+            impl XfastKey for $key {
+                const BITS: usize = <$key>::BITS as usize;
+
+                fn to_bits(self) -> u128 {
+                    self as u128
+                }
+
+                fn from_bits(bits: u128) -> Self {
+                    bits as $key
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! signed_key {
+    ($($key:ty => $unsigned:ty),*) => {
+        $(
+            impl XfastKey for $key {
+                const BITS: usize = <$key>::BITS as usize;
+
+                fn to_bits(self) -> u128 {
+                    // flipping the sign bit moves the negative keys below the non-negative ones
+                    ((self as $unsigned) ^ (1 << (<$key>::BITS - 1))) as u128
+                }
+
+                fn from_bits(bits: u128) -> Self {
+                    ((bits as $unsigned) ^ (1 << (<$key>::BITS - 1))) as $key
+                }
+            }
+        )*
+    };
+}
+
+unsigned_key!(u8, u16, u32, u64, u128, usize);
+signed_key!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize);
+
+#[cfg(test)]
+mod test {
+    use super::XfastKey;
+
+    #[test]
+    fn signed_order() -> Result<(), String> {
+        let keys = [i64::MIN, -5, -1, 0, 1, i64::MAX];
+        let encoded: Vec<u128> = keys.iter().map(|&key| key.to_bits()).collect();
+        let decoded: Vec<i64> = encoded.iter().map(|&bits| i64::from_bits(bits)).collect();
+        if encoded.windows(2).all(|pair| pair[0] < pair[1]) && decoded == keys {
+            Ok(())
+        }
+        else {
+            Err(String::from("Signed keys should be encoded in order"))
+        }
+    }
+
+    #[test]
+    fn wide_keys() -> Result<(), String> {
+        if u128::MAX.to_bits() == u128::MAX && i128::from_bits(i128::MIN.to_bits()) == i128::MIN {
+            Ok(())
+        }
+        else {
+            Err(String::from("128 bit keys should round trip"))
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Storage of the node indices at every level of the trie, keyed by their prefix.
+//!
+//! By default every level has a hash map of its own. The top levels can instead be addressed
+//! directly, each kept in an array with a slot for every prefix of the level. The flat mode keeps the nodes of all the
+//! levels in a single open-addressing table instead, keyed by the pair of the level and the
+//! prefix. Its slots hold the node indices inline and collisions are resolved by linear probing,
+//! so a probe of the LCA search touches one allocation, and the table grows as a whole rather than
+//! rehashing one level at a time. Removals shift the entries after the removed one back, so the
+//! table never fills up with tombstones.
+
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+
+use crate::{Node, Xfast, XfastKey};
+
+// level of a free slot, past the deepest level of a 128 bit key
+const FREE: u8 = u8::MAX;
+
+// deepest cutoff of the directly addressed levels, whose arrays take 2^24 node slots
+const MAX_DIRECT_LEVELS: usize = 24;
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    prefix: u128,
+    node: Node,
+    level: u8,
+}
+
+const FREE_SLOT: Slot = Slot { prefix: 0, node: 0, level: FREE };
+
+// single open-addressing table holding the nodes of every level
+#[derive(Debug)]
+pub(crate) struct FlatTable<S> {
+    // a power of two count of slots, at most three quarters of them in use
+    slots: Vec<Slot>,
+    // count of nodes at every level
+    counts: Vec<usize>,
+    len: usize,
+    hash_builder: S,
+}
+
+impl<S: BuildHasher> FlatTable<S> {
+    fn with_capacity_and_hasher(nr_levels: usize, capacity: usize, hash_builder: S) -> Self {
+        FlatTable {
+            slots: vec![FREE_SLOT; Self::slot_count(capacity)],
+            counts: vec![0; nr_levels + 1],
+            len: 0,
+            hash_builder,
+        }
+    }
+
+    // slots needed to hold `capacity` entries under the load limit
+    fn slot_count(capacity: usize) -> usize {
+        (capacity + capacity / 3 + 1).next_power_of_two().max(8)
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    // slot the probe sequence of the pair starts from
+    fn home(&self, level: u8, prefix: u128) -> usize {
+        let mut hasher = self.hash_builder.build_hasher();
+        hasher.write_u128(prefix);
+        hasher.write_u8(level);
+        hasher.finish() as usize & self.mask()
+    }
+
+    // slot holding the pair, or the free slot ending its probe sequence
+    fn find(&self, level: u8, prefix: u128) -> Result<usize, usize> {
+        let mut index = self.home(level, prefix);
+        loop {
+            let slot = &self.slots[index];
+            if slot.level == FREE {
+                return Err(index);
+            }
+            if slot.level == level && slot.prefix == prefix {
+                return Ok(index);
+            }
+            index = (index + 1) & self.mask();
+        }
+    }
+
+    fn get(&self, level: usize, prefix: u128) -> Option<Node> {
+        self.find(level as u8, prefix).ok().map(|index| self.slots[index].node)
+    }
+
+    fn insert(&mut self, level: usize, prefix: u128, node: Node) -> Option<Node> {
+        let free = match self.find(level as u8, prefix) {
+            Ok(index) => return Some(std::mem::replace(&mut self.slots[index].node, node)),
+            Err(free) => free,
+        };
+        let free = if (self.len + 1) * 4 > self.slots.len() * 3 {
+            self.grow();
+            self.find(level as u8, prefix).expect_err("pair inserted while growing")
+        }
+        else {
+            free
+        };
+        self.slots[free] = Slot { prefix, node, level: level as u8 };
+        self.counts[level] += 1;
+        self.len += 1;
+        None
+    }
+
+    fn remove(&mut self, level: usize, prefix: u128) -> Option<Node> {
+        let mut hole = self.find(level as u8, prefix).ok()?;
+        let node = self.slots[hole].node;
+        // shift back the entries whose probe sequence runs through the hole
+        let mut next = (hole + 1) & self.mask();
+        while self.slots[next].level != FREE {
+            let home = self.home(self.slots[next].level, self.slots[next].prefix);
+            if next.wrapping_sub(home) & self.mask() >= next.wrapping_sub(hole) & self.mask() {
+                self.slots[hole] = self.slots[next];
+                hole = next;
+            }
+            next = (next + 1) & self.mask();
+        }
+        self.slots[hole] = FREE_SLOT;
+        self.counts[level] -= 1;
+        self.len -= 1;
+        Some(node)
+    }
+
+    // double the count of slots and place every entry again
+    fn grow(&mut self) {
+        let grown = vec![FREE_SLOT; self.slots.len() * 2];
+        let slots = std::mem::replace(&mut self.slots, grown);
+        for slot in slots.into_iter().filter(|slot| slot.level != FREE) {
+            let free = self.find(slot.level, slot.prefix).expect_err("pair placed twice");
+            self.slots[free] = slot;
+        }
+    }
+}
+
+// hashed storage of the levels below the directly addressed ones
+#[derive(Debug)]
+enum Hashed<S> {
+    PerLevel(Vec<HashMap<u128, Node, S>>),
+    Flat(FlatTable<S>),
+}
+
+impl<S: BuildHasher> Hashed<S> {
+    fn hasher(&self) -> &S {
+        match self {
+            Hashed::PerLevel(maps) => maps[0].hasher(),
+            Hashed::Flat(table) => &table.hash_builder,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        match self {
+            Hashed::PerLevel(maps) => maps.iter().map(|map| map.capacity()).sum(),
+            Hashed::Flat(table) => table.slots.len() * 3 / 4,
+        }
+    }
+}
+
+// node indices of every level keyed by their prefix
+#[derive(Debug)]
+pub(crate) struct Levels<S> {
+    // nodes of the levels above the cutoff indexed by their prefix, NO_NODE where there's none
+    direct: Vec<Vec<Node>>,
+    hashed: Hashed<S>,
+}
+
+// empty entry of a directly addressed level
+const NO_NODE: Node = Node::MAX;
+
+impl<S: BuildHasher + Clone> Levels<S> {
+    // per level maps for levels 0 to `nr_levels` with room for `capacity` keys
+    pub(crate) fn new(nr_levels: usize, capacity: usize, hash_builder: S) -> Self {
+        // a level can't hold more nodes than it has prefixes
+        let maps = (0..=nr_levels)
+            .map(|level| HashMap::with_capacity_and_hasher(capacity.min(1usize.checked_shl(level as u32).unwrap_or(usize::MAX)), hash_builder.clone()))
+            .collect();
+        Levels { direct: Vec::new(), hashed: Hashed::PerLevel(maps) }
+    }
+
+    // move the nodes over to the storage asked for, hashed in a flat table if `flat` and with
+    // `direct_levels` levels addressed directly
+    pub(crate) fn set_layout(&mut self, flat: bool, direct_levels: usize) {
+        if flat == self.is_flat() && direct_levels == self.direct_levels() {
+            return;
+        }
+        let nr_levels = self.nr_levels();
+        let hash_builder = self.hasher().clone();
+        let hashed = if flat {
+            // keep the room reserved in the maps
+            Hashed::Flat(FlatTable::with_capacity_and_hasher(nr_levels, self.hashed.capacity(), hash_builder))
+        }
+        else {
+            Levels::new(nr_levels, 0, hash_builder).hashed
+        };
+        let mut levels = Levels {
+            direct: (0..direct_levels).map(|level| vec![NO_NODE; 1 << level]).collect(),
+            hashed,
+        };
+        for (level, prefix, node) in self.entries() {
+            levels.insert(level, prefix, node);
+        }
+        *self = levels;
+    }
+}
+
+impl<S: BuildHasher> Levels<S> {
+    pub(crate) fn is_flat(&self) -> bool {
+        matches!(self.hashed, Hashed::Flat(_))
+    }
+
+    // count of levels from the root down which are addressed directly
+    pub(crate) fn direct_levels(&self) -> usize {
+        self.direct.len()
+    }
+
+    // deepest level, holding the leaves
+    pub(crate) fn nr_levels(&self) -> usize {
+        match &self.hashed {
+            Hashed::PerLevel(maps) => maps.len() - 1,
+            Hashed::Flat(table) => table.counts.len() - 1,
+        }
+    }
+
+    pub(crate) fn hasher(&self) -> &S {
+        self.hashed.hasher()
+    }
+
+    pub(crate) fn get(&self, level: usize, prefix: u128) -> Option<Node> {
+        if let Some(direct) = self.direct.get(level) {
+            // prefixes of keys beyond the range of the trie fall past the end of the level
+            return direct.get(prefix as usize).copied().filter(|&node| node != NO_NODE);
+        }
+        match &self.hashed {
+            Hashed::PerLevel(maps) => maps[level].get(&prefix).copied(),
+            Hashed::Flat(table) => table.get(level, prefix),
+        }
+    }
+
+    // node stored at `level` for `prefix`, which has to be present
+    pub(crate) fn node(&self, level: usize, prefix: u128) -> Node {
+        self.get(level, prefix).expect("node missing from its level")
+    }
+
+    pub(crate) fn contains(&self, level: usize, prefix: u128) -> bool {
+        self.get(level, prefix).is_some()
+    }
+
+    pub(crate) fn insert(&mut self, level: usize, prefix: u128, node: Node) -> Option<Node> {
+        if let Some(direct) = self.direct.get_mut(level) {
+            // only the prefixes of keys within the range of the trie are ever inserted
+            let previous = std::mem::replace(&mut direct[prefix as usize], node);
+            return if previous == NO_NODE { None } else { Some(previous) };
+        }
+        match &mut self.hashed {
+            Hashed::PerLevel(maps) => maps[level].insert(prefix, node),
+            Hashed::Flat(table) => table.insert(level, prefix, node),
+        }
+    }
+
+    pub(crate) fn remove(&mut self, level: usize, prefix: u128) -> Option<Node> {
+        if let Some(direct) = self.direct.get_mut(level) {
+            let previous = direct.get_mut(prefix as usize).map(|slot| std::mem::replace(slot, NO_NODE));
+            return previous.filter(|&node| node != NO_NODE);
+        }
+        match &mut self.hashed {
+            Hashed::PerLevel(maps) => maps[level].remove(&prefix),
+            Hashed::Flat(table) => table.remove(level, prefix),
+        }
+    }
+
+    // count of nodes at `level`
+    pub(crate) fn len(&self, level: usize) -> usize {
+        if let Some(direct) = self.direct.get(level) {
+            return direct.iter().filter(|&&node| node != NO_NODE).count();
+        }
+        match &self.hashed {
+            Hashed::PerLevel(maps) => maps[level].len(),
+            Hashed::Flat(table) => table.counts[level],
+        }
+    }
+
+    // level, prefix and node of every entry, ordered by level from the root down
+    pub(crate) fn entries(&self) -> Vec<(usize, u128, Node)> {
+        let mut entries: Vec<_> = self.direct.iter().enumerate()
+            .flat_map(|(level, direct)| direct.iter().enumerate()
+                .filter(|&(_, &node)| node != NO_NODE)
+                .map(move |(prefix, &node)| (level, prefix as u128, node)))
+            .collect();
+        match &self.hashed {
+            Hashed::PerLevel(maps) => entries.extend(maps.iter().enumerate()
+                .flat_map(|(level, map)| map.iter().map(move |(&prefix, &node)| (level, prefix, node)))),
+            Hashed::Flat(table) => {
+                let mut hashed: Vec<_> = table.slots.iter().filter(|slot| slot.level != FREE)
+                    .map(|slot| (slot.level as usize, slot.prefix, slot.node))
+                    .collect();
+                hashed.sort_by_key(|&(level, _, _)| level);
+                entries.extend(hashed);
+            }
+        }
+        entries
+    }
+}
+
+impl<K: XfastKey, V, S: BuildHasher + Clone> Xfast<K, V, S> {
+
+    /// Keeps the nodes of every level in a single open-addressing table keyed by the level and
+    /// the prefix, instead of one hash map per level. The LCA search then probes a single table
+    /// which grows as a whole.
+    ///
+    /// Removals get faster, but the top levels are no longer kept in small maps of their own
+    /// which stay in cache, so inserts and queries are usually slower than with the level maps.
+    /// `cargo run --release --example level_storage` compares both on your machine.
+    /// # Examples
+    /// ```
+    ///     use xfast::{BuildIntHasher, Xfast};
+    ///
+    ///     let mut test_trie: Xfast<u64, &str, _> = Xfast::with_hasher(1 << 40, BuildIntHasher::default()).with_flat_table();
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1 << 39, "large");
+    ///     assert_eq!(test_trie.successor(12), Some((1 << 39, &"large")));
+    ///     test_trie.remove(1 << 39);
+    ///     assert_eq!(test_trie.successor(12), None);
+    /// ```
+    pub fn with_flat_table(mut self) -> Self {
+        let direct_levels = self.levels.direct_levels();
+        self.levels.set_layout(true, direct_levels);
+        self
+    }
+
+    /// Stores the levels above `cutoff` in arrays indexed directly by the prefix, and starts
+    /// the LCA search from the deepest of those levels holding an ancestor of the key, so that
+    /// prefixes are only hashed below the cutoff. It pays off when the top levels are mostly
+    /// full, as with dense sets of keys. The arrays take `2^cutoff` node slots, and a cutoff past
+    /// the levels of the trie keeps every level but the leaves in arrays.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<u32, u32> = Xfast::new(u32::MAX).with_direct_levels(12);
+    ///     for key in (0..1 << 20).step_by(97) {
+    ///         test_trie.insert(key, key);
+    ///     }
+    ///     assert_eq!(test_trie.successor(1000), Some((1067, &1067)));
+    ///     assert_eq!(test_trie.predecessor(1000), Some((970, &970)));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `cutoff` is more than 24 levels.
+    pub fn with_direct_levels(mut self, cutoff: usize) -> Self {
+        assert!(cutoff <= MAX_DIRECT_LEVELS, "direct levels can't go below level {}", MAX_DIRECT_LEVELS);
+        let flat = self.levels.is_flat();
+        self.levels.set_layout(flat, cutoff.min(self.nr_levels));
+        self
+    }
+
+    // empty trie with the same universe, hasher and level storage
+    pub(crate) fn empty_with_same_levels(&self) -> Self {
+        let mut empty = Self::with_levels_and_hasher(self.nr_levels, 0, self.hasher().clone());
+        empty.levels.set_layout(self.levels.is_flat(), self.levels.direct_levels());
+        empty
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BuildIntHasher, Xfast};
+
+    #[test]
+    fn flat_table_same_as_maps() -> Result<(), String> {
+        let mut maps: Xfast<u64, u64> = Xfast::new(u64::MAX >> 4);
+        let mut flat: Xfast<u64, u64, _> = Xfast::with_hasher(u64::MAX >> 4, BuildIntHasher::with_seed(5)).with_flat_table();
+        let keys: Vec<u64> = (0..3000u64).map(|key| key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 4).collect();
+        for (index, &key) in keys.iter().enumerate() {
+            maps.insert(key, index as u64);
+            flat.insert(key, index as u64);
+        }
+        // removals shift entries back across the probe sequences of the others
+        for &key in keys.iter().filter(|&&key| key % 3 != 0) {
+            maps.remove(key);
+            flat.remove(key);
+        }
+        let queries = (0..1000u64).map(|query| query.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 4);
+        let same = queries.clone().all(|query| maps.successor(query) == flat.successor(query) && maps.predecessor(query) == flat.predecessor(query));
+        let levels = (0..=maps.nr_levels).all(|level| maps.levels.len(level) == flat.levels.len(level));
+        if same && levels && maps.iter().eq(flat.iter()) && flat.levels.entries().len() == maps.levels.entries().len() {
+            Ok(())
+        }
+        else {
+            Err(String::from("The flat table should hold the same nodes as the level maps"))
+        }
+    }
+
+    #[test]
+    fn direct_levels_same_as_maps() -> Result<(), String> {
+        let mut maps: Xfast<u32, u32> = Xfast::new(u32::MAX >> 8);
+        let mut direct: Xfast<u32, u32> = Xfast::new(u32::MAX >> 8).with_direct_levels(10);
+        let mut flat: Xfast<u32, u32, _> = Xfast::with_hasher(u32::MAX >> 8, BuildIntHasher::with_seed(9)).with_direct_levels(6).with_flat_table();
+        // dense keys fill the direct levels, while the queries past them run through empty ones
+        let keys: Vec<u32> = (0..2000u32).map(|key| key.wrapping_mul(0x9e37_79b9) >> 20).chain(1 << 23..(1 << 23) + 50).collect();
+        for &key in keys.iter() {
+            maps.insert(key, key);
+            direct.insert(key, key);
+            flat.insert(key, key);
+        }
+        for &key in keys.iter().filter(|&&key| key % 5 == 0) {
+            maps.remove(key);
+            direct.remove(key);
+            flat.remove(key);
+        }
+        let upper = direct.split_off(1 << 11);
+        // queries beyond the range of the tries fall past the end of the direct levels
+        let same = (0..1 << 14).chain((1 << 23) - 3..(1 << 23) + 60).chain((1 << 24) - 2..(1 << 24) + 2).chain(Some(u32::MAX)).all(|query| {
+            maps.successor(query) == direct.successor(query).or_else(|| upper.successor(query))
+                && maps.predecessor(query) == flat.predecessor(query)
+                && maps.successor(query) == flat.successor(query)
+        });
+        let mut small: Xfast<u32, u32> = Xfast::new(1023).with_direct_levels(4);
+        small.insert(3, 3);
+        let beyond = small.successor(5000).is_none() && small.range(3..5000).count() == 1 && small.split_off(5000).is_empty()
+            && small.remove(5000).is_none() && small.predecessor(5000) == Some((3, &3));
+        // a single direct level holds only the root, above every hashed level
+        let mut root_only: Xfast<u32, u32> = Xfast::new(1023).with_direct_levels(1);
+        root_only.insert(3, 3);
+        root_only.insert(700, 700);
+        let beyond = beyond && root_only.predecessor(5000) == Some((700, &700)) && root_only.successor(5000).is_none()
+            && root_only.successor(4) == Some((700, &700)) && root_only.predecessor(699) == Some((3, &3)) && root_only.remove(5000).is_none();
+        let levels = (0..=maps.nr_levels).all(|level| maps.levels.len(level) == flat.levels.len(level));
+        let clamped: Xfast<u8, u8> = Xfast::default().with_direct_levels(20);
+        if same && beyond && levels && upper.levels.direct_levels() == 10 && clamped.levels.direct_levels() == 8 && maps.iter().eq(flat.iter()) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Tries with direct levels should hold the same keys"))
+        }
+    }
+
+    #[test]
+    fn switch_storage_mode() -> Result<(), String> {
+        let test_trie: Xfast<u16, u16> = Xfast::from_sorted_iter(1023, (0..1024).step_by(7).map(|key| (key, key)));
+        let mut flat = test_trie.with_flat_table();
+        let upper = flat.split_off(500);
+        flat.levels.set_layout(false, 0);
+        let kept = flat.iter().map(|(key, &value)| (key, value)).eq((0..500).step_by(7).map(|key| (key, key)));
+        if kept && upper.levels.is_flat() && upper.len() == 75 && upper.successor(501) == Some((504, &504)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Tries should keep their keys across storage modes"))
+        }
+    }
+}
@@ -1,99 +1,110 @@
-#![feature(box_into_raw_non_null)]
 #![allow(dead_code)]
-#![feature(map_get_key_value)]
 
 //! [X-fast Trie](https://en.wikipedia.org/wiki/X-fast_trie) is a bitwise trie to store a bounded domain of integers.
 //!
-//! Currently this version of the crate is using the nightly release of rust.
+//! The trie nodes are kept in an index-based arena, so the crate builds on stable rust.
+//!
+//! The `serde` feature implements `Serialize` and `Deserialize` for [`Xfast`] and [`XfastSet`].
+
+mod algebra;
+mod arena;
+mod build;
+mod concurrent;
+mod entry;
+mod hamt;
+mod hash;
+mod key;
+mod levels;
+mod persistent;
+mod range;
+mod set;
+mod sharded;
+mod snapshot;
+#[cfg(feature = "serde")]
+mod serialize;
+mod split;
+mod stats;
+mod veb;
+mod yfast;
+mod zfast;
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::RangeBounds;
+
+use arena::{Arena, LeafValues};
+use levels::Levels;
 
-use std::collections::HashMap;
-use std::ptr::NonNull;
+pub use algebra::{Difference, Gaps, Intersection, SymmetricDifference, Union};
+pub use build::FromSortedError;
+pub use concurrent::ConcurrentXfast;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use hash::{BuildIntHasher, IntHasher};
+pub use key::XfastKey;
+pub use persistent::{PersistentXfast, PersistentXfastIter};
+pub use range::{XfastRange, XfastRangeMut};
+pub use set::{SetDifference, SetGaps, SetIntersection, SetSymmetricDifference, SetUnion, XfastSet, XfastSetIter};
+pub use sharded::{ShardedXfast, ShardedXfastRange};
+pub use snapshot::{SnapshotError, SnapshotSection, ValueCodec};
+pub use veb::{VebIter, VebTree};
+pub use yfast::{Yfast, YfastIter};
+pub use zfast::{ZfastIter, ZfastTrie};
 
-type Node<T> = NonNull<TrieNode<T>>;
-/// Nodes of the trie
+// index of a node in the trie's arena
+pub(crate) type Node = usize;
+
+// Nodes of the trie, the values of the leaves are kept apart in `LeafValues`
 #[derive(Debug)]
-pub struct TrieNode<T> {
-    // Node key
-    key: usize,
-    /// Node value
-    pub value: Option<T>,
+struct TrieNode {
+    // Node key in its order preserving encoding
+    key: u128,
     // Level at which the node is present
     level: usize,
     // Node's right subtree
-    right: Option<NonNull<TrieNode<T>>>,
+    right: Option<Node>,
     // Node's left subtree
-    left: Option<NonNull<TrieNode<T>>>,
+    left: Option<Node>,
     // Node's metadata about descendant node status
     is_desc_left: bool,
     is_desc_right: bool,
 }
 
-impl<T> TrieNode<T> {
-    
-    /// Creates a new node of the trie with `key` and `value` and no children.
-    pub fn new(key: usize, value: T, level: usize) -> Box<Self> {
-        Box::new(TrieNode{
-            key: key,
-            value: Some(value),
-            level: level,
+impl TrieNode {
+
+    // constructor for leaves holding `key`
+    fn new(key: u128, level: usize) -> Self {
+        TrieNode {
+            key,
+            level,
             right: None,
             left: None,
             is_desc_right: true,
             is_desc_left: true,
-        })
+        }
     }
 
     // constructor for internal nodes
-    fn new_internal(level: usize) -> Box<Self> {
-        Box::new(TrieNode{
+    fn new_internal(level: usize) -> Self {
+        TrieNode {
             key: 0,
-            value: None,
             level,
             right: None,
             left: None,
             is_desc_left: true,
             is_desc_right: true,
-        })
+        }
     }
 
-    // return the rightmost node for @cur_node as parent
-    // @max_level: max possible height of the trie
-    fn get_rightmost_node(max_level: usize, mut cur_node: *mut TrieNode<T>) -> Option<Node<T>> {
-        unsafe {
-            while (*cur_node).level != max_level {
-                match (*cur_node).right {
-                    Some(right_node) => {
-                        cur_node = right_node.as_ptr();
-                    }
-                    None => {
-                        (*cur_node).left.map(|left_node| {
-                            cur_node = left_node.as_ptr();
-                        });
-                    }
-                }
-            }
-            NonNull::new(cur_node as *mut TrieNode<T>)
-        }
+    // left child of the node, ignoring a descendant ptr stored in its place
+    fn left_child(&self) -> Option<Node> {
+        if self.is_desc_left { None } else { self.left }
     }
 
-    // return the leftmost node for @cur_node as parent
-    // @max_level: max possible height of the trie
-    fn get_leftmost_node(max_level: usize, mut cur_node: *mut TrieNode<T>) -> Option<Node<T>> {
-        unsafe {
-            while (*cur_node).level != max_level {
-                match (*cur_node).left {
-                    Some(left_node) => {
-                        cur_node = left_node.as_ptr();
-                    }
-                    None => {
-                        (*cur_node).right.map(|right_node| {
-                            cur_node = right_node.as_ptr();
-                        });
-                    }
-                }
-            }
-            NonNull::new(cur_node as *mut TrieNode<T>)
-        }
+    // right child of the node, ignoring a descendant ptr stored in its place
+    fn right_child(&self) -> Option<Node> {
+        if self.is_desc_right { None } else { self.right }
     }
 }
 
@@ -104,67 +115,141 @@ impl<T> TrieNode<T> {
 /// only if it has leaves in its subtree.
 ///
 /// Each level of the trie is modelled as a hash map storing the trie nodes at that level.
+/// The nodes themselves are owned by the trie and are freed along with it. The maps hash the
+/// prefixes with SipHash unless another hasher is given through `S`, see
+/// [`with_hasher`](Xfast::with_hasher).
 ///
-/// The range of integers need to be specified while initializing a trie.
+/// Keys can be any integer type implementing [`XfastKey`]. The range of keys can be specified while
+/// initializing a trie, otherwise the trie spans the full width of the key type.
 /// # Examples
 /// ```
 ///     use xfast::Xfast;
 ///
-///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-///     test_trie.insert_key(11, "eleven");
-///     test_trie.insert_key(1, "one");
-///     test_trie.insert_key(5, "five");
+///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+///     test_trie.insert(11, "eleven");
+///     test_trie.insert(1, "one");
+///     test_trie.insert(5, "five");
 ///     assert_eq!(test_trie.len(), 3);
-///        
-///     let predecessor_3 = test_trie.find_predecessor(3);
-///     if predecessor_3.is_some() {
-///         let predecessor_value = predecessor_3.unwrap().value.unwrap();
-///         assert_eq!(predecessor_value, "one");
-///     }
+///
+///     assert_eq!(test_trie.predecessor(3), Some((1, &"one")));
+///     assert_eq!(test_trie.successor(3), Some((5, &"five")));
 /// ```
-
-pub struct Xfast<T=String> {
+pub struct Xfast<K, V, S = RandomState> {
     nr_levels: usize,
-    level_maps: Vec<HashMap<usize, NonNull<TrieNode<T>>>>,
+    // node indices at every level keyed by their prefix
+    levels: Levels<S>,
+    // storage for all the nodes of the trie
+    nodes: Arena<TrieNode>,
+    // values of the leaves, at the arena index of their leaf
+    values: LeafValues<V>,
+    // leaves holding the smallest and the largest keys
+    min_leaf: Option<Node>,
+    max_leaf: Option<Node>,
+    // count of leaves below every internal node, indexed by node, when order statistics are kept
+    subtree_sizes: Option<Vec<usize>>,
+    key_type: PhantomData<K>,
 }
 
-impl<T> Xfast<T> {
-    
-    /// Creates a new Xfast Trie to store a given `range` of integers
+impl<K: XfastKey, V> Xfast<K, V> {
+
+    /// Creates a new Xfast Trie to store keys from zero up to `range`.
+    ///
+    /// For signed keys the range applies to their order preserving encoding, so any non-negative
+    /// `range` spans the full width of the key type.
     /// # Examples
     /// ```
     /// # #![allow(unused_mut)]
     ///     use xfast::Xfast;
-    ///     
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
     /// ```
-    pub fn new(range: usize) -> Self {
-        let nr_levels = Self::get_levels_count(range);
-        let level_maps = Self::create_map_list(nr_levels+1);
+    pub fn new(range: K) -> Self {
+        Self::with_hasher(range, RandomState::new())
+    }
+
+    // trie with leaves at depth `nr_levels`
+    fn with_levels(nr_levels: usize) -> Self {
+        Self::with_levels_and_hasher(nr_levels, 0, RandomState::new())
+    }
+}
+
+impl<K: XfastKey, V, S: BuildHasher + Clone> Xfast<K, V, S> {
+
+    /// Creates a new Xfast Trie to store keys from zero up to `range`, hashing the prefixes at
+    /// every level with `hash_builder`.
+    ///
+    /// Every lookup probes the level maps with integer prefixes, so a fast integer hasher such as
+    /// [`BuildIntHasher`] speeds up the trie when the keys don't come from an adversary.
+    /// # Examples
+    /// ```
+    ///     use xfast::{BuildIntHasher, Xfast};
+    ///
+    ///     let mut test_trie: Xfast<u32, &str, BuildIntHasher> = Xfast::with_hasher(1023, BuildIntHasher::default());
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(5, "five");
+    ///     assert_eq!(test_trie.successor(6), Some((11, &"eleven")));
+    /// ```
+    pub fn with_hasher(range: K, hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(range, 0, hash_builder)
+    }
+
+    /// Creates a new Xfast Trie to store keys from zero up to `range` with room for at least
+    /// `capacity` keys before its level maps reallocate, hashing the prefixes with `hash_builder`.
+    /// # Examples
+    /// ```
+    ///     use xfast::{BuildIntHasher, Xfast};
+    ///
+    ///     let mut test_trie: Xfast<u32, u32, _> = Xfast::with_capacity_and_hasher(1023, 100, BuildIntHasher::with_seed(7));
+    ///     for key in 0..100 {
+    ///         test_trie.insert(key * 10, key);
+    ///     }
+    ///     assert_eq!(test_trie.predecessor(995), Some((990, &99)));
+    /// ```
+    pub fn with_capacity_and_hasher(range: K, capacity: usize, hash_builder: S) -> Self {
+        Self::with_levels_and_hasher(Self::get_levels_count(range.to_bits()), capacity, hash_builder)
+    }
+
+    // trie with leaves at depth `nr_levels` and room for `capacity` keys
+    fn with_levels_and_hasher(nr_levels: usize, capacity: usize, hash_builder: S) -> Self {
         let mut new_trie = Xfast {
             nr_levels,
-            level_maps,
+            levels: Levels::new(nr_levels, capacity, hash_builder),
+            nodes: Arena::new(),
+            values: LeafValues::new(),
+            min_leaf: None,
+            max_leaf: None,
+            subtree_sizes: None,
+            key_type: PhantomData,
         };
         // insert the root node in the trie at level 0
-        let root_node = TrieNode::new_internal(0);
-        let root_node = Box::into_raw_non_null(root_node);
-        new_trie.level_maps[0].insert(0, root_node);
+        let root_node = new_trie.nodes.alloc(TrieNode::new_internal(0));
+        new_trie.levels.insert(0, 0, root_node);
         new_trie
     }
+}
+
+impl<K: XfastKey, V, S: BuildHasher> Xfast<K, V, S> {
+
+    /// Returns the hasher building the hashes of the prefixes at every level
+    pub fn hasher(&self) -> &S {
+        self.levels.hasher()
+    }
 
     // levels => height of the trie
-    fn get_levels_count(mut range: usize) -> usize {
+    fn get_levels_count(mut range: u128) -> usize {
+        // a trie needs at least one level below the root to hold its leaves
         let mut levels = 0;
         while range > 0 {
             range >>= 1;
             levels += 1;
         }
-        levels
+        levels.max(1)
     }
 
-    // helper fn for populating a vector list of hashmaps
-    fn create_map_list(nr_levels: usize) -> Vec<HashMap<usize, Node<T>>> {
-        (0..nr_levels).map(|_| HashMap::new()).collect()
+    // prefix of `key` identifying its ancestor at `level`
+    fn prefix(&self, key: u128, level: usize) -> u128 {
+        // the root sits above every bit of a full width key
+        key.checked_shr((self.nr_levels - level) as u32).unwrap_or(0)
     }
 
     /// Returns the count of values stored in the trie
@@ -172,28 +257,56 @@ impl<T> Xfast<T> {
     /// ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
-    ///     test_trie.insert_key(1, "one");
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
     ///     assert_eq!(test_trie.len(), 2);
     /// ```
     pub fn len(&self) -> usize {
-        self.level_maps[self.nr_levels].len()
+        self.levels.len(self.nr_levels)
     }
 
-    fn find_lowest_common_ancestor(&self, key: usize) -> Option<*mut TrieNode<T>> {
-        let mut low = 0;
-        let mut high = self.nr_levels;
-        let mut ancestor_node: Option<*mut TrieNode<T>> = None;
+    /// Returns true if the trie stores no values
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // largest key that fits in the range of the trie
+    fn max_key(&self) -> u128 {
+        u128::MAX >> (128 - self.nr_levels)
+    }
+
+    fn find_lowest_common_ancestor(&self, key: u128) -> Option<Node> {
+        // the direct levels are scanned upwards from the deepest one, an array lookup per level,
+        // and the search starts at the first of them holding an ancestor: only an ancestor at the
+        // deepest direct level leaves the hashed levels below to search
+        let direct_levels = self.levels.direct_levels();
+        if direct_levels > 0 {
+            for level in (0..direct_levels).rev() {
+                if let Some(node) = self.levels.get(level, self.prefix(key, level)) {
+                    if level + 1 < direct_levels {
+                        return Some(node);
+                    }
+                    return self.search_levels(key, direct_levels, self.nr_levels).or(Some(node));
+                }
+            }
+            return None;
+        }
+        self.search_levels(key, 0, self.nr_levels)
+    }
+
+    // deepest ancestor of `key` between levels `low` and `high`
+    fn search_levels(&self, key: u128, mut low: usize, mut high: usize) -> Option<Node> {
+        let mut ancestor_node: Option<Node> = None;
 
         while high >= low {
             let mid = (low + high)/2;
-            let prefix = key >> (self.nr_levels - mid);
-            //check the presence of an internal node with the keyed as `prefix` in hashmap at the `mid` level 
-            match self.level_maps[mid].get(&prefix) {
-                Some(&value) => {
+            let prefix = self.prefix(key, mid);
+            //check the presence of an internal node with the keyed as `prefix` at the `mid` level
+            match self.levels.get(mid, prefix) {
+                Some(node) => {
                     low = mid + 1;
-                    ancestor_node = Some(value.as_ptr());
+                    ancestor_node = Some(node);
                 }
                 None => {
                     // prevent out of bound subtraction of a usize
@@ -207,538 +320,741 @@ impl<T> Xfast<T> {
         ancestor_node
     }
 
-    /// Returns the smallest node more than or eqaul to the node associated with `key`. In case of no such node it returns None.
-    /// #Examples
+    // leaf holding the smallest key >= `key`
+    fn successor_node(&self, key: u128) -> Option<Node> {
+        // no key of the trie follows a key beyond its range
+        if key > self.max_key() {
+            return None;
+        }
+        // find the lowest common ancestor- a node which shares maximum common prefix with the key
+        let node = self.find_lowest_common_ancestor(key)?;
+        let ancestor = &self.nodes[node];
+        // successor of a key already present is the key itself
+        if ancestor.level == self.nr_levels {
+            return Some(node);
+        }
+
+        //right subtree of an internal node can have the successor
+        let updated_node = if (key >> (self.nr_levels - ancestor.level - 1)) & 1 != 0 {
+            ancestor.right?
+        }
+        else {
+            //left subtree of the internal node has the successor
+            ancestor.left?
+        };
+
+        // in case the key of the successor node (leaf node) above calculated has lower key than the currently searched key
+        // navigate using the right pointer of the leaf node to find the smallest node which has a key >= the key being searched
+        if self.nodes[updated_node].key < key {
+            return self.nodes[updated_node].right;
+        }
+        Some(updated_node)
+    }
+
+    // leaf holding the largest key <= `key`
+    fn predecessor_node(&self, key: u128) -> Option<Node> {
+        // every key of the trie precedes a key beyond its range
+        if key > self.max_key() {
+            return self.predecessor_node(self.max_key());
+        }
+        // find the lowest common ancestor- a node which shares maximum common prefix with the key
+        let node = self.find_lowest_common_ancestor(key)?;
+        let ancestor = &self.nodes[node];
+        // predecessor of a key already present is the key itself
+        if ancestor.level == self.nr_levels {
+            return Some(node);
+        }
+
+        let updated_node = if (key >> (self.nr_levels - ancestor.level - 1)) & 1 != 0 {
+            ancestor.right?
+        }
+        else {
+            ancestor.left?
+        };
+
+        if self.nodes[updated_node].key > key {
+            return self.nodes[updated_node].left;
+        }
+        Some(updated_node)
+    }
+
+    /// Returns the smallest key more than or equal to `key` along with its value. In case of no such key it returns None.
     /// # Examples
     /// ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
-    ///     test_trie.insert_key(1, "one");
-    ///     test_trie.insert_key(5, "five");
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     test_trie.insert(5, "five");
     ///     assert_eq!(test_trie.len(), 3);
-    ///     
-    ///     if let Some(successor_3) = test_trie.find_successor(3) {
-    ///         let successor_value = successor_3.value.unwrap();
-    ///         assert_eq!(successor_value, "five");
-    ///     }
-    ///     let successor_14 = test_trie.find_successor(14);
-    ///         assert!(successor_14.is_none());
+    ///
+    ///     assert_eq!(test_trie.successor(3), Some((5, &"five")));
+    ///     assert_eq!(test_trie.successor(5), Some((5, &"five")));
+    ///     assert!(test_trie.successor(14).is_none());
     /// ```
-    pub fn find_successor(&self, key: usize) -> Option<&TrieNode<T>> {
-        // find the lowest common ancestor- a node which shares maximum common prefix with the key
-        let successor_node: Option<*mut TrieNode<T>> = self.find_lowest_common_ancestor(key);
-        if let Some(node) = successor_node {
-            unsafe {
-                // successor of a key already present is the key itself
-                if (*node).level == (self.nr_levels) {
-                    return Some(&(*node));
-                }
+    pub fn successor(&self, key: K) -> Option<(K, &V)> {
+        self.successor_node(key.to_bits()).map(|node| self.leaf_key_value(node))
+    }
 
-                //right subtree of an internal node can have the successor
-                let mut updated_node = None;
-                if (key >> (self.nr_levels - (*node).level -1 ) & 1) != 0 {
-                    (*node).right.map(|right_node| {
-                        updated_node = Some(right_node.as_ptr());
-                    });
-                }
-                else {
-                    //left subtree of the internal node has the successor
-                    (*node).left.map(|left_node| {
-                        updated_node = Some(left_node.as_ptr());
-                    });
-                }
-                                
-                // in case the key of the successor node (leaf node) above calculated has lower key than the currently searched key
-                // navigate using the right and left pointer of the leaf node to find the smallest node which has a key >= the key being searched
-                if !updated_node.is_none() && (*updated_node.unwrap()).key < key {
-                    let mut temp_node = None;
-                    (*updated_node.unwrap()).right.map(|right_node| {
-                        temp_node = Some(&(*right_node.as_ptr()));
-                    });
-                    return temp_node;
-                }
-                if !updated_node.is_none() {
-                    return Some(&(*updated_node.unwrap()));
-                }
-                return None;
-            }
-        }
-        None
+    /// Returns the smallest key more than or equal to `key` along with a mutable reference to its value.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, u32> = Xfast::new(31);
+    ///     test_trie.insert(11, 0);
+    ///     if let Some((_, value)) = test_trie.successor_mut(3) {
+    ///         *value += 1;
+    ///     }
+    ///     assert_eq!(test_trie.get(11), Some(&1));
+    /// ```
+    pub fn successor_mut(&mut self, key: K) -> Option<(K, &mut V)> {
+        let node = self.successor_node(key.to_bits())?;
+        Some(self.leaf_key_value_mut(node))
     }
 
-    /// Returns the largest node less that or eqaul to the node with `key`. In case of no such node it returns None.
-    /// #Examples
+    /// Returns the largest key less than or equal to `key` along with its value. In case of no such key it returns None.
     /// # Examples
     /// ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
-    ///     test_trie.insert_key(1, "one");
-    ///     test_trie.insert_key(5, "five");
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     test_trie.insert(5, "five");
     ///     assert_eq!(test_trie.len(), 3);
-    ///        
-    ///     if let Some(predecessor_3) = test_trie.find_predecessor(3) {
-    ///         let predecessor_value = predecessor_3.value.unwrap();
-    ///         assert_eq!(predecessor_value, "one");
-    ///     }
     ///
-    ///     let predecessor_0 = test_trie.find_predecessor(0);
-    ///         assert!(predecessor_0.is_none());
+    ///     assert_eq!(test_trie.predecessor(3), Some((1, &"one")));
+    ///     assert!(test_trie.predecessor(0).is_none());
     /// ```
-    pub fn find_predecessor(&self, key: usize) -> Option<&TrieNode<T>> {
-        // find the lowest common ancestor- a node which shares maximum common prefix with the key
-        let predecessor_node: Option<*mut TrieNode<T>> = self.find_lowest_common_ancestor(key);
-        if let Some(node) = predecessor_node {
-            unsafe {
-                // predecessor of a key already present is the key itself
-                if (*node).level == (self.nr_levels) {
-                    return Some(&(*node));
-                }
+    pub fn predecessor(&self, key: K) -> Option<(K, &V)> {
+        self.predecessor_node(key.to_bits()).map(|node| self.leaf_key_value(node))
+    }
 
-                let mut updated_node = None;
-                if (key >> (self.nr_levels - (*node).level -1) & 1) != 0 {
-                    (*node).right.map(|right_node| {
-                        updated_node = Some(right_node.as_ptr());
-                    });
-                }
-                else {
-                    (*node).left.map(|left_node| {
-                        updated_node = Some(left_node.as_ptr());
-                    });
-                }
+    /// Returns the largest key less than or equal to `key` along with a mutable reference to its value.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, u32> = Xfast::new(31);
+    ///     test_trie.insert(1, 0);
+    ///     if let Some((_, value)) = test_trie.predecessor_mut(3) {
+    ///         *value += 1;
+    ///     }
+    ///     assert_eq!(test_trie.get(1), Some(&1));
+    /// ```
+    pub fn predecessor_mut(&mut self, key: K) -> Option<(K, &mut V)> {
+        let node = self.predecessor_node(key.to_bits())?;
+        Some(self.leaf_key_value_mut(node))
+    }
 
-                if !updated_node.is_none() && (*updated_node.unwrap()).key > key {
-                    let mut temp_node = None;
-                    (*updated_node.unwrap()).left.map(|left_node| {
-                        temp_node = Some(&(*left_node.as_ptr()));
-                    });
-                    return temp_node;
-                }
-                if !updated_node.is_none() {
-                return Some(&(*updated_node.unwrap()));
-                }
-                return None;
-                }
-            }
-        None
+    // make `child` the left (`bit` == 0) or right (`bit` == 1) child of `parent`
+    fn link_child(&mut self, parent: Node, bit: u128, child: Node) {
+        let parent_node = &mut self.nodes[parent];
+        if bit != 0 {
+            parent_node.right = Some(child);
+            parent_node.is_desc_right = false;
+        }
+        else {
+            parent_node.left = Some(child);
+            parent_node.is_desc_left = false;
+        }
     }
 
-    fn populate_internal_nodes(&mut self, key: usize) {
-        let mut level = 1;
-        let max_levels = self.nr_levels;
-        while level < max_levels {
-            let prefix = key >> (max_levels - level);
-            if let None = self.level_maps[level].get(&prefix) {
-                let temp_node = TrieNode::new_internal(level);
-                let temp_node = Box::into_raw_non_null(temp_node);
-                self.level_maps[level].insert(prefix, temp_node);
+    // drop the left (`bit` == 0) or right (`bit` == 1) child of `parent`
+    fn unlink_child(&mut self, parent: Node, bit: u128) {
+        let parent_node = &mut self.nodes[parent];
+        if bit != 0 {
+            parent_node.right = None;
+            parent_node.is_desc_right = true;
+        }
+        else {
+            parent_node.left = None;
+            parent_node.is_desc_left = true;
+        }
+    }
+
+    fn populate_internal_nodes(&mut self, key: u128) {
+        for level in 1..self.nr_levels {
+            let prefix = self.prefix(key, level);
+            if !self.levels.contains(level, prefix) {
+                let temp_node = self.nodes.alloc(TrieNode::new_internal(level));
+                self.levels.insert(level, prefix, temp_node);
                 // add to the right child if the bit is 1 at that index else make it the left child
-                if (prefix & 1) != 0 {
-                    let temp_prefix = prefix >> 1;
-                    self.level_maps[level-1].get(&temp_prefix).map(|&value| unsafe{
-                        (*value.as_ptr()).right = Some(temp_node);
-                        (*value.as_ptr()).is_desc_right = false;
-                    });
-                }
-                else {
-                    let temp_prefix = prefix >> 1;
-                    self.level_maps[level-1].get(&temp_prefix).map(|&value| unsafe{
-                        (*value.as_ptr()).left = Some(temp_node);
-                        (*value.as_ptr()).is_desc_left = false;
-                    }); 
-                }
+                let parent = self.levels.node(level-1, prefix >> 1);
+                self.link_child(parent, prefix & 1, temp_node);
             }
-            level += 1;
-        }
-    }
-
-    fn update_descendant_ptr(&mut self, key: usize) {
-        let mut prefix = key;
-        let mut level = self.nr_levels - 1;
-
-        while level > 0 {
-            prefix = prefix >> 1;
-            // find an internal node prefixed as `prefix` at `level` in the level_map
-            self.level_maps[level].get(&prefix).map(|&value| unsafe {
-                //check if this node has a left child
-                match (*value.as_ptr()).left {
-                    //the internal node doesn't have a left child
-                    None => {
-                        //An internal node is inserted in a trie only when it has one its children
-                        //Therefore, this node has a right child which is used to find its descendant ptr
-                        (*value.as_ptr()).right.map(|right_node| {
-                            (*value.as_ptr()).left = TrieNode::get_leftmost_node(self.nr_levels, right_node.as_ptr());
-                            (*value.as_ptr()).is_desc_left = true;
-                        });
-                    },
-                    // Left child is present
-                    Some(left_ptr) => {
-                        //this internal node can have a right child or not
-                        match (*value.as_ptr()).right {
-                            // the right child is not present
-                            None => {
-                                (*value.as_ptr()).right = TrieNode::get_rightmost_node(self.nr_levels, left_ptr.as_ptr());
-                                (*value.as_ptr()).is_desc_right = true;
-                            }
-                            // right child is also present
-                            Some(right_ptr)=> {
-                                // if any of the left or the right child is associated with a descendant pointer then update with the latest descendant pointer. 
-                                // At any given instance only one descendant ptr can be present
-                                if (*value.as_ptr()).is_desc_right {
-                                    (*value.as_ptr()).right = TrieNode::get_rightmost_node(self.nr_levels, left_ptr.as_ptr());
-                                }
-                                else if (*value.as_ptr()).is_desc_left {
-                                    (*value.as_ptr()).left = TrieNode::get_leftmost_node(self.nr_levels, right_ptr.as_ptr());
-                                }
-                            }
-                        }
-                    }
-                }
-            });
-            level -= 1;
-        }
-
-        // update the descendant ptr for the root node
-        self.level_maps[0].get(&0).map(|&value| unsafe {
-            let is_left_descendant = (*value.as_ptr()).is_desc_left;
-            let is_right_descendant = (*value.as_ptr()).is_desc_right;
-            if is_left_descendant {
-                (*value.as_ptr()).right.map(|right_node| {
-                    (*value.as_ptr()).left = TrieNode::get_leftmost_node(self.nr_levels, right_node.as_ptr());
-                });
+        }
+    }
+
+    // true if the leaf `neighbour` shares the ancestor `prefix` at `level`
+    fn shares_subtree(&self, neighbour: Option<Node>, level: usize, prefix: u128) -> bool {
+        neighbour.is_some_and(|node| self.prefix(self.nodes[node].key, level) == prefix)
+    }
+
+    // point the descendant ptrs on the path of the new `leaf` at it wherever it became the smallest
+    // or largest leaf of the subtree, which is where its neighbours in the list of leaves aren't
+    fn descendant_ptrs_after_insert(&mut self, leaf: Node) {
+        let key = self.nodes[leaf].key;
+        let (predecessor, successor) = (self.nodes[leaf].left, self.nodes[leaf].right);
+        for level in (0..self.nr_levels).rev() {
+            let prefix = self.prefix(key, level);
+            let smallest = !self.shares_subtree(predecessor, level, prefix);
+            let largest = !self.shares_subtree(successor, level, prefix);
+            // both neighbours are below this node, and so below all the nodes above it
+            if !smallest && !largest {
+                break;
+            }
+            let node = self.levels.node(level, prefix);
+            let trie_node = &mut self.nodes[node];
+            if trie_node.is_desc_left && smallest {
+                trie_node.left = Some(leaf);
             }
-            if is_right_descendant {
-                (*value.as_ptr()).left.map(|left_node| {
-                    (*value.as_ptr()).right = TrieNode::get_rightmost_node(self.nr_levels, left_node.as_ptr());
-                });
+            if trie_node.is_desc_right && largest {
+                trie_node.right = Some(leaf);
             }
-        });
+        }
     }
 
-    /// Insert `key` and `value` into the trie
+    // repoint the descendant ptrs on the path of the removed `leaf` which referred to it, or to a
+    // child removed along with it, at the neighbours it had in the list of leaves
+    fn descendant_ptrs_after_remove(&mut self, leaf: Node, predecessor: Option<Node>, successor: Option<Node>) {
+        let key = self.nodes[leaf].key;
+        for level in (0..self.nr_levels).rev() {
+            let prefix = self.prefix(key, level);
+            if self.shares_subtree(predecessor, level, prefix) && self.shares_subtree(successor, level, prefix) {
+                break;
+            }
+            // the nodes left with only the removed leaf are gone
+            let node = match self.levels.get(level, prefix) {
+                Some(node) => node,
+                None => continue,
+            };
+            let trie_node = &mut self.nodes[node];
+            if trie_node.is_desc_left && trie_node.left.is_none_or(|left| left == leaf) {
+                trie_node.left = successor;
+            }
+            if trie_node.is_desc_right && trie_node.right.is_none_or(|right| right == leaf) {
+                trie_node.right = predecessor;
+            }
+        }
+    }
+
+    /// Insert `key` and `value` into the trie. If the key was already present its value is replaced
+    /// in place and the previous value is returned, otherwise it returns None.
+    /// # Panics
+    /// Panics if `key` is larger than the range the trie was created with.
     /// # Examples
     /// ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     assert!(test_trie.insert(11, "eleven").is_none());
+    ///     assert_eq!(test_trie.insert(11, "xi"), Some("eleven"));
+    ///     assert_eq!(test_trie.len(), 1);
     /// ```
-    pub fn insert_key(&mut self, key: usize, value: T) {
-        //create a new node with key and val
-        let new_node = TrieNode::new(key, value, self.nr_levels);
-        let new_node = Some(Box::into_raw_non_null(new_node));
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let key = key.to_bits();
+        // an existing leaf keeps its place in the trie and only swaps its value
+        if let Some(leaf) = self.leaf_node(key) {
+            return Some(std::mem::replace(self.values.get_mut(leaf), value));
+        }
+        self.insert_leaf(key, value);
+        None
+    }
+
+    // add a leaf for `key`, which must not be present in the trie yet
+    pub(crate) fn insert_leaf(&mut self, key: u128, value: V) -> Node {
+        assert!(self.prefix(key, 0) == 0, "key out of the range of the trie");
+
         //find predecessor and successor for the new node
-        let predecessor = self.find_predecessor(key);
-        let successor = self.find_successor(key);
-        
+        let predecessor = self.predecessor_node(key);
+        let successor = self.successor_node(key);
+        //create a new node with key and val
+        let new_node = self.nodes.alloc(TrieNode::new(key, self.nr_levels));
+        self.values.insert(new_node, value);
+
         //update the right and left pointers of the new node to refer to its successors and predecessors resp.
         //update the right ptr in the predecessor ,and left ptr in the successor with the new_node.
-        predecessor.map(|pred_node| unsafe{
-            //FIXME
-            let pred_node = &(*pred_node) as *const TrieNode<T> as *mut TrieNode<T>;
-            new_node.map(|node| {
-                (*node.as_ptr()).right = (*pred_node).right;
-                (*node.as_ptr()).left = NonNull::new(pred_node);
-            });
-            (*pred_node).right = new_node;
-        });
-
-        successor.map(|suc_node| unsafe{
-            let suc_node = &(*suc_node) as *const TrieNode<T> as *mut TrieNode<T>;
-            new_node.map(|node| {
-                (*node.as_ptr()).left = (*suc_node).left;
-                (*node.as_ptr()).right = NonNull::new(suc_node);
-            });
-            (*suc_node).left = new_node;
-        });
+        //a new node without a predecessor (successor) becomes the smallest (largest) leaf
+        match predecessor {
+            Some(pred_node) => {
+                self.nodes[new_node].left = Some(pred_node);
+                self.nodes[pred_node].right = Some(new_node);
+            }
+            None => self.min_leaf = Some(new_node),
+        }
+        match successor {
+            Some(suc_node) => {
+                self.nodes[new_node].right = Some(suc_node);
+                self.nodes[suc_node].left = Some(new_node);
+            }
+            None => self.max_leaf = Some(new_node),
+        }
 
         //populate intermediate iternal nodes on the path down the new_node
         self.populate_internal_nodes(key);
-        
+        self.update_subtree_sizes(key, true);
+
         //insert the new_node at the last level and update the ptr of its parent node using the prefix bit
-        self.level_maps[self.nr_levels].insert(key, new_node.unwrap());
-        let temp_key = key >> 1;
-        self.level_maps[self.nr_levels-1].get(&temp_key).map(|&value| unsafe {
-            if (key & 1) != 0 {
-                (*value.as_ptr()).right= new_node;
-                (*value.as_ptr()).is_desc_right = false;
-            }
-            else {
-                (*value.as_ptr()).left = new_node;
-                (*value.as_ptr()).is_desc_left = false;
-            }
-        });
+        self.levels.insert(self.nr_levels, key, new_node);
+        let parent = self.levels.node(self.nr_levels-1, key >> 1);
+        self.link_child(parent, key & 1, new_node);
 
-        // update descendant ptrs
-        self.update_descendant_ptr(key);
+        self.descendant_ptrs_after_insert(new_node);
+        new_node
     }
 
-    fn delete_internal_node(&mut self, key: usize) {
-        let mut level = self.nr_levels-1;
-        let mut prefix = key;
+    fn delete_internal_node(&mut self, key: u128) {
         let mut child_prefix = key;
 
-        while level > 0 {
-            prefix = prefix >> 1;
-            if let Some(internal_node) = self.level_maps[level].get(&prefix) {
-                unsafe {
-                    //check if it has a descendant node
-                    if (child_prefix &1) == 1 {
-                        //check left node
-                        if !(*internal_node.as_ptr()).is_desc_left {
-                            break;
-                        }
-                    }
-                    else if !(*internal_node.as_ptr()).is_desc_right {
-                            break;
-                    }
-                }
+        for level in (0..self.nr_levels).rev() {
+            let prefix = child_prefix >> 1;
+            let internal_node = self.levels.node(level, prefix);
+            self.unlink_child(internal_node, child_prefix & 1);
+
+            // the node still has leaves in its other subtree; the root is never removed
+            let node = &self.nodes[internal_node];
+            if level == 0 || !node.is_desc_left || !node.is_desc_right {
+                break;
             }
-                    
-            let parent_prefix = prefix >> 1;
-            self.level_maps[level-1].get(&parent_prefix).map(|parent_node| unsafe{
-                //node present in right subtree
-                if (prefix & 1) != 0 {
-                    (*parent_node.as_ptr()).right = None;
-                    (*parent_node.as_ptr()).is_desc_right = true;
-                }
-                else {
-                    (*parent_node.as_ptr()).left = None;
-                    (*parent_node.as_ptr()).is_desc_left = true;
-                }
-            });
-            self.level_maps[level].remove(&prefix);
-            child_prefix = child_prefix>>1;
-            level -= 1;
+            self.levels.remove(level, prefix);
+            self.nodes.free(internal_node);
+            child_prefix = prefix;
         }
     }
 
-    /// Delete a key from the trie. If the node doesn't exist it returns None else retuns the deleted   `TrieNode` wrapped in a `NonNull` struct.
+    /// Delete a key from the trie. If the key doesn't exist it returns None else returns the value
+    /// that was stored with it.
     /// # Examples
     ///  ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
-    ///     test_trie.insert_key(1, "one");
-    ///     test_trie.insert_key(5, "five");
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     test_trie.insert(5, "five");
     ///     assert_eq!(test_trie.len(), 3);
-    ///        
-    ///     test_trie.delete_key(5);
+    ///
+    ///     assert_eq!(test_trie.remove(5), Some("five"));
     ///     assert_eq!(test_trie.len(), 2);
-    ///     assert!(test_trie.delete_key(2).is_none());
+    ///     assert!(test_trie.remove(2).is_none());
     ///     assert_eq!(test_trie.len(), 2);
     /// ```
-    pub fn delete_key(&mut self, key: usize) -> Option<Node<T>>{
+    pub fn remove(&mut self, key: K) -> Option<V> {
         //find the key in the lowest level
-        let deleted_node = self.find_key_as_non_null(key);
-        if deleted_node.is_none() {
-            return None;
+        let deleted_node = self.leaf_node(key.to_bits())?;
+        Some(self.remove_leaf(deleted_node))
+    }
+
+    // remove the leaf `deleted_node` from the trie and return its value
+    pub(crate) fn remove_leaf(&mut self, deleted_node: Node) -> V {
+        let key = self.nodes[deleted_node].key;
+        self.update_subtree_sizes(key, false);
+        self.levels.remove(self.nr_levels, key);
+
+        // unlink the leaf from the linked list of leaves
+        let predecessor_node = self.nodes[deleted_node].left;
+        let successor_node = self.nodes[deleted_node].right;
+        match predecessor_node {
+            Some(pred_node) => self.nodes[pred_node].right = successor_node,
+            None => self.min_leaf = successor_node,
+        }
+        match successor_node {
+            Some(suc_node) => self.nodes[suc_node].left = predecessor_node,
+            None => self.max_leaf = predecessor_node,
         }
 
-        let deleted_node = deleted_node.unwrap();
-        
-        self.level_maps[self.nr_levels-1].get(&(key>>1)).map(|internal_node| unsafe{
-            if (key &1) == 1 {
-                (*internal_node.as_ptr()).right = None;
-                (*internal_node.as_ptr()).is_desc_right = true;
-            }
-            else {
-                (*internal_node.as_ptr()).left = None;
-                (*internal_node.as_ptr()).is_desc_left = true;
-            }
-        });
-        
         self.delete_internal_node(key);
-        unsafe {
-            let predecessor_node = (*deleted_node.as_ptr()).left;
-            let successor_node = (*deleted_node.as_ptr()).right;
-            
-            if !predecessor_node.is_none() {
-                (*predecessor_node.unwrap().as_ptr()).right = successor_node;
-            }
-            if !successor_node.is_none() {
+        self.descendant_ptrs_after_remove(deleted_node, predecessor_node, successor_node);
+        self.nodes.free(deleted_node);
+        self.values.remove(deleted_node)
+    }
 
-                (*successor_node.unwrap().as_ptr()).left = predecessor_node;
-            }
+    // leaf holding `key`
+    pub(crate) fn leaf_node(&self, key: u128) -> Option<Node> {
+        self.levels.get(self.nr_levels, key)
+    }
+
+    // leaf holding the smallest key of the trie
+    pub(crate) fn first_leaf(&self) -> Option<Node> {
+        self.min_leaf
+    }
+
+    // leaf holding the largest key of the trie
+    pub(crate) fn last_leaf(&self) -> Option<Node> {
+        self.max_leaf
+    }
+
+    /// Returns the smallest key of the trie along with its value in constant time.
+    /// # Examples
+    ///  ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     assert!(test_trie.first_key_value().is_none());
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     assert_eq!(test_trie.first_key_value(), Some((1, &"one")));
+    /// ```
+    pub fn first_key_value(&self) -> Option<(K, &V)> {
+        self.min_leaf.map(|node| self.leaf_key_value(node))
+    }
+
+    /// Returns the largest key of the trie along with its value in constant time.
+    /// # Examples
+    ///  ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     assert_eq!(test_trie.last_key_value(), Some((11, &"eleven")));
+    /// ```
+    pub fn last_key_value(&self) -> Option<(K, &V)> {
+        self.max_leaf.map(|node| self.leaf_key_value(node))
+    }
+
+    /// Removes the smallest key from the trie and returns it along with its value.
+    /// # Examples
+    ///  ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut queue: Xfast<u64, &str> = Xfast::default();
+    ///     queue.insert(30, "later");
+    ///     queue.insert(10, "now");
+    ///     assert_eq!(queue.pop_first(), Some((10, "now")));
+    ///     assert_eq!(queue.pop_first(), Some((30, "later")));
+    ///     assert!(queue.pop_first().is_none());
+    /// ```
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let node = self.min_leaf?;
+        let key = self.leaf_key(node);
+        Some((key, self.remove_leaf(node)))
+    }
+
+    /// Removes the largest key from the trie and returns it along with its value.
+    /// # Examples
+    ///  ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     assert_eq!(test_trie.pop_last(), Some((11, "eleven")));
+    ///     assert_eq!(test_trie.last_key_value(), Some((1, &"one")));
+    /// ```
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let node = self.max_leaf?;
+        let key = self.leaf_key(node);
+        Some((key, self.remove_leaf(node)))
+    }
+
+    // key stored in the leaf `node`
+    pub(crate) fn leaf_key(&self, node: Node) -> K {
+        K::from_bits(self.nodes[node].key)
+    }
+
+    // value stored in the leaf `node`
+    pub(crate) fn leaf_value(&self, node: Node) -> &V {
+        self.values.get(node)
+    }
+
+    // mutable value stored in the leaf `node`
+    pub(crate) fn leaf_value_mut(&mut self, node: Node) -> &mut V {
+        self.values.get_mut(node)
+    }
+
+    // key-value pair stored in the leaf `node`
+    fn leaf_key_value(&self, node: Node) -> (K, &V) {
+        (self.leaf_key(node), self.values.get(node))
+    }
+
+    // key and mutable value stored in the leaf `node`
+    fn leaf_key_value_mut(&mut self, node: Node) -> (K, &mut V) {
+        (K::from_bits(self.nodes[node].key), self.values.get_mut(node))
+    }
+
+    /// Gets the entry of `key` for in-place manipulation.
+    /// # Examples
+    ///  ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut counts: Xfast<u8, u32> = Xfast::default();
+    ///     for &byte in b"abracadabra".iter() {
+    ///         *counts.entry(byte).or_insert(0) += 1;
+    ///     }
+    ///     assert_eq!(counts.get(b'a'), Some(&5));
+    ///     assert_eq!(counts.get(b'c'), Some(&1));
+    /// ```
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.leaf_node(key.to_bits()) {
+            Some(node) => Entry::Occupied(OccupiedEntry::new(self, node)),
+            None => Entry::Vacant(VacantEntry::new(self, key)),
         }
-        let deleted_node = self.level_maps[self.nr_levels].remove(&key);
-        self.update_descendant_ptr(key);
-        deleted_node
     }
 
-    fn find_key_as_non_null(&self, key: usize) -> Option<Node<T>> {
-        self.level_maps[self.nr_levels].get(&key).map(|&value| {
-            value
-        })
+    /// Returns a reference to the value stored with `key`
+    /// # Examples
+    ///  ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     assert_eq!(test_trie.get(1), Some(&"one"));
+    ///     assert!(test_trie.get(2).is_none());
+    /// ```
+    pub fn get(&self, key: K) -> Option<&V> {
+        self.get_key_value(key).map(|(_, value)| value)
     }
 
-    /// Find a key in the trie
+    /// Returns a mutable reference to the value stored with `key`
     /// # Examples
     ///  ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
-    ///     test_trie.insert_key(1, "one");
-    ///     if let Some(node_1) = test_trie.find_key(1) {
-    ///         // all the leaf nodes values have non trivial values and assert is_some. 
-    ///         // So unwrapping will not panic
-    ///         assert_eq!(node_1.value.unwrap(), "one");
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(1, "one");
+    ///     if let Some(value) = test_trie.get_mut(1) {
+    ///         *value = "uno";
     ///     }
+    ///     assert_eq!(test_trie.get(1), Some(&"uno"));
     /// ```
-    pub fn find_key(&self, key: usize) -> Option<&TrieNode<T>> {
-        self.level_maps[self.nr_levels].get(&key).map(|&value| unsafe {
-            &(*value.as_ptr())
-        })
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        let node = self.leaf_node(key.to_bits())?;
+        Some(self.values.get_mut(node))
     }
 
-    /// Returns an iterator around all the key-TrieNode pairs stored in the trie.
+    /// Returns the key stored in the trie along with its value
     /// # Examples
+    ///  ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     assert_eq!(test_trie.get_key_value(11), Some((11, &"eleven")));
     /// ```
+    pub fn get_key_value(&self, key: K) -> Option<(K, &V)> {
+        self.leaf_node(key.to_bits()).map(|node| self.leaf_key_value(node))
+    }
+
+    /// Returns true if the trie stores a value for `key`
+    /// # Examples
+    ///  ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
-    ///     test_trie.insert_key(1, "one");
-    ///     test_trie.insert_key(19, "nineteen");
-    ///     for (key, node) in test_trie.iter() {
-    ///         println!("key: {} value: {:?}", key, node);
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     assert!(test_trie.contains_key(11));
+    ///     assert!(!test_trie.contains_key(12));
+    /// ```
+    pub fn contains_key(&self, key: K) -> bool {
+        self.levels.contains(self.nr_levels, key.to_bits())
+    }
+
+    /// Returns a double-ended iterator around the key-value pairs with keys in `range`, in ascending
+    /// order of keys.
+    ///
+    /// The ends of the range are found by a successor and a predecessor search, after which the
+    /// iterator follows the links between the leaves.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     test_trie.insert(19, "nineteen");
+    ///     test_trie.insert(5, "five");
+    ///     let keys: Vec<usize> = test_trie.range(2..19).map(|(key, _)| key).collect();
+    ///     assert_eq!(keys, vec![5, 11]);
+    ///     assert_eq!(test_trie.range(..=11).next_back(), Some((11, &"eleven")));
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> XfastRange<'_, K, V> {
+        XfastRange::new(&self.nodes, &self.values, self.leaf_bounds(range))
+    }
+
+    /// Returns a double-ended iterator around the keys in `range` and their mutable values, in
+    /// ascending order of keys.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, u32> = Xfast::new(31);
+    ///     for key in 0..10 {
+    ///         test_trie.insert(key, 0);
+    ///     }
+    ///     for (_, value) in test_trie.range_mut(3..6) {
+    ///         *value += 1;
     ///     }
+    ///     assert_eq!(test_trie.get(5), Some(&1));
+    ///     assert_eq!(test_trie.get(6), Some(&0));
     /// ```
-    pub fn iter(&self) -> XfastIter<T> {
-        let leaf_map = &self.level_maps[self.nr_levels];
-        let mut keys: Vec<usize> = vec!();
-        
-        for &cur_key in leaf_map.keys() {
-            keys.push(cur_key);
-        }
+    pub fn range_mut<R: RangeBounds<K>>(&mut self, range: R) -> XfastRangeMut<'_, K, V> {
+        let bounds = self.leaf_bounds(range);
+        XfastRangeMut::new(&self.nodes, &mut self.values, bounds)
+    }
 
+    /// Returns an iterator around all the key-value pairs stored in the trie, in ascending order of keys.
+    ///
+    /// The iterator walks the linked list of leaves starting from the smallest key, so it doesn't allocate.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     test_trie.insert(19, "nineteen");
+    ///     for (key, value) in test_trie.iter() {
+    ///         println!("key: {} value: {}", key, value);
+    ///     }
+    ///
+    ///     let keys: Vec<usize> = test_trie.iter().rev().map(|(key, _)| key).collect();
+    ///     assert_eq!(keys, vec![19, 11, 1]);
+    /// ```
+    pub fn iter(&self) -> XfastIter<'_, K, V> {
         XfastIter {
-            leaf_map,
-            keys,
-            index: 0,
+            range: self.range(..),
+            len: self.len(),
         }
     }
 
-    /// Returns a mutable iterator around all the key-TrieNode pairs stored in the trie.
+    /// Returns an iterator around all the keys and mutable values stored in the trie, in ascending order of keys.
     /// # Examples
     /// ```
     ///     use xfast::Xfast;
     ///
-    ///     let mut test_trie: Xfast<&str> = Xfast::new(31);
-    ///     test_trie.insert_key(11, "eleven");
-    ///     test_trie.insert_key(1, "one");
-    ///     test_trie.insert_key(19, "nineteen");
-    ///     for (key, node) in test_trie.iter_mut() {
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     test_trie.insert(19, "nineteen");
+    ///     for (key, value) in test_trie.iter_mut() {
     ///         if key % 2 == 1 {
-    ///             node.value = Some("updated_odd");    
+    ///             *value = "updated_odd";
     ///         }
     ///     }
     ///
-    ///     if let Some(node_1) = test_trie.find_key(1) {
-    ///         assert_eq!(node_1.value.unwrap(), "updated_odd");
-    ///     }
+    ///     assert_eq!(test_trie.get(1), Some(&"updated_odd"));
     /// ```
-    pub fn iter_mut(&mut self) -> XfastIterMut<T> {
-        let leaf_map = &self.level_maps[self.nr_levels];
-        let mut keys: Vec<usize> = vec!();
-        
-        for &cur_key in leaf_map.keys() {
-            keys.push(cur_key);
-        }
-
+    pub fn iter_mut(&mut self) -> XfastIterMut<'_, K, V> {
+        let len = self.len();
         XfastIterMut {
-            leaf_map,
-            keys,
-            index: 0,
+            range: self.range_mut(..),
+            len,
         }
     }
 }
 
-/// Iterator around the Xfast key and value (TrieNode) pairs
+impl<K: XfastKey, V, S: BuildHasher + Clone + Default> Default for Xfast<K, V, S> {
+    /// Creates an empty trie spanning the full width of the key type
+    fn default() -> Self {
+        Self::with_levels_and_hasher(K::BITS, 0, S::default())
+    }
+}
 
-pub struct XfastIter<'a, T> {
-    leaf_map: &'a HashMap<usize, Node<T>>,
-    keys: Vec<usize>,
-    index: usize,
+/// Iterator around the Xfast key and value pairs in ascending order of keys
+pub struct XfastIter<'a, K, V> {
+    range: XfastRange<'a, K, V>,
+    // count of pairs yet to be yielded
+    len: usize,
 }
 
-impl<'a, T> Iterator for XfastIter<'a, T> {
-    type Item = (&'a usize, &'a TrieNode<T>);
+impl<'a, K: XfastKey, V> Iterator for XfastIter<'a, K, V> {
+    type Item = (K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.leaf_map.len() {
-            let key = self.keys[self.index];
-            self.index += 1;
-            let kv_pair = self.leaf_map.get_key_value(&key);
-            kv_pair.map(|(key, value)| unsafe{
-                let value = value.as_ref();
-                (key, value)
-            })
-        }
-        else {
-            None
-        }
+        let item = self.range.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, K: XfastKey, V> DoubleEndedIterator for XfastIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.range.next_back()?;
+        self.len -= 1;
+        Some(item)
     }
 }
 
-/// Mutable Iterator around the Xfast key and value (TrieNode) pairs
-pub struct XfastIterMut<'a, T> {
-    leaf_map: &'a HashMap<usize, Node<T>>,
-    keys: Vec<usize>,
-    index: usize,
+impl<'a, K: XfastKey, V> ExactSizeIterator for XfastIter<'a, K, V> {}
+
+impl<'a, K: XfastKey, V> FusedIterator for XfastIter<'a, K, V> {}
+
+/// Mutable Iterator around the Xfast key and value pairs in ascending order of keys
+pub struct XfastIterMut<'a, K, V> {
+    range: XfastRangeMut<'a, K, V>,
+    len: usize,
 }
 
-impl<'a, T> Iterator for XfastIterMut<'a, T> {
-    type Item = (&'a usize, &'a mut TrieNode<T>);
+impl<'a, K: XfastKey, V> Iterator for XfastIterMut<'a, K, V> {
+    type Item = (K, &'a mut V);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index < self.leaf_map.len() {
-            let key = self.keys[self.index];
-            self.index += 1;
-            let kv_pair = self.leaf_map.get_key_value(&key);
-            kv_pair.map(|(key, value)| unsafe{
-                let value = &mut (*value.as_ptr());
-                (key, value)
-            })
-        }
-        else {
-            None
-        }
+        let item = self.range.next()?;
+        self.len -= 1;
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
     }
 }
 
-impl<'a, T> IntoIterator for &'a Xfast<T> {
-    type Item = (&'a usize, &'a TrieNode<T>);
-    type IntoIter = XfastIter<'a, T>;
-    fn into_iter(self) -> XfastIter<'a, T> {
+impl<'a, K: XfastKey, V> DoubleEndedIterator for XfastIterMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let item = self.range.next_back()?;
+        self.len -= 1;
+        Some(item)
+    }
+}
+
+impl<'a, K: XfastKey, V> ExactSizeIterator for XfastIterMut<'a, K, V> {}
+
+impl<'a, K: XfastKey, V> FusedIterator for XfastIterMut<'a, K, V> {}
+
+impl<'a, K: XfastKey, V, S: BuildHasher> IntoIterator for &'a Xfast<K, V, S> {
+    type Item = (K, &'a V);
+    type IntoIter = XfastIter<'a, K, V>;
+    fn into_iter(self) -> XfastIter<'a, K, V> {
         self.iter()
     }
 }
 
+impl<'a, K: XfastKey, V, S: BuildHasher> IntoIterator for &'a mut Xfast<K, V, S> {
+    type Item = (K, &'a mut V);
+    type IntoIter = XfastIterMut<'a, K, V>;
+    fn into_iter(self) -> XfastIterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
 
 
 mod test{
     use super::Xfast;
 
-    fn init()  -> Xfast<String> {
-        let mut test_trie: Xfast<String> = Xfast::new(31);
-        test_trie.insert_key(11, String::from("eleven"));
-        test_trie.insert_key(1, String::from("one"));
-        test_trie.insert_key(18, String::from("eighteen"));
-        test_trie.insert_key(5, String::from("five"));
+    fn init()  -> Xfast<usize, String> {
+        let mut test_trie: Xfast<usize, String> = Xfast::new(31);
+        test_trie.insert(11, String::from("eleven"));
+        test_trie.insert(1, String::from("one"));
+        test_trie.insert(18, String::from("eighteen"));
+        test_trie.insert(5, String::from("five"));
         test_trie
     }
 
     #[test]
     fn successor() -> Result<(), String> {
         let test_trie = init();
-        if let Some(successor) = test_trie.find_successor(7) {
-            if successor.key == 11 {
+        if let Some((key, _)) = test_trie.successor(7) {
+            if key == 11 {
                 return Ok(())
             }
         }
@@ -748,7 +1064,7 @@ mod test{
     #[test]
     fn none_successor() -> Result<(), String> {
         let test_trie = init();
-        if test_trie.find_successor(19).is_none() {
+        if test_trie.successor(19).is_none() {
             Ok(())
         }
         else {
@@ -759,8 +1075,8 @@ mod test{
     #[test]
     fn predecessor() -> Result<(), String> {
         let test_trie = init();
-        if let Some(predecessor) = test_trie.find_predecessor(8) {
-            if predecessor.key == 5 {
+        if let Some((key, _)) = test_trie.predecessor(8) {
+            if key == 5 {
                 return Ok(())
             }
         }
@@ -770,7 +1086,7 @@ mod test{
     #[test]
     fn none_predecessor() -> Result<(), String> {
         let test_trie = init();
-        if test_trie.find_predecessor(0).is_none() {
+        if test_trie.predecessor(0).is_none() {
             Ok(())
         }
         else {
@@ -781,8 +1097,8 @@ mod test{
     #[test]
     fn find_key_present() -> Result<(), String> {
         let test_trie = init();
-        if let Some(value) = test_trie.find_key(11) {
-            if value.key == 11 {
+        if let Some(value) = test_trie.get(11) {
+            if value == "eleven" {
                     return Ok(());
             }
         }
@@ -792,29 +1108,46 @@ mod test{
     #[test]
     fn find_key_not_present() -> Result<(), String> {
         let test_trie = init();
-        if test_trie.find_key(7).is_none() {
+        if test_trie.get(7).is_none() {
             return Ok(());
         }
         Err(String::from("Key should not have been present"))
     }
 
+    #[test]
+    fn mutable_neighbours() -> Result<(), String> {
+        let mut test_trie = init();
+        if let Some((_, value)) = test_trie.successor_mut(12) {
+            value.push('!');
+        }
+        if let Some((_, value)) = test_trie.predecessor_mut(4) {
+            value.push('?');
+        }
+        if test_trie.get(18).map(String::as_str) == Some("eighteen!") && test_trie.get(1).map(String::as_str) == Some("one?") {
+            Ok(())
+        }
+        else {
+            Err(String::from("Neighbours should have been updated in place"))
+        }
+    }
+
     #[test]
     fn delete_node() -> Result<(), String> {
         let mut test_trie = init();
-        test_trie.delete_key(18);
-        if test_trie.find_key(18).is_none() {
+        test_trie.remove(18);
+        if test_trie.get(18).is_none() {
             Ok(())
         }
         else {
             Err(String::from("Key should have been deleted"))
-        }   
+        }
     }
 
     #[test]
     fn successor_after_del() -> Result<(), String> {
         let mut test_trie = init();
-        test_trie.delete_key(18);
-        if test_trie.find_successor(18).is_none() {
+        test_trie.remove(18);
+        if test_trie.successor(18).is_none() {
             Ok(())
         }
         else {
@@ -825,9 +1158,9 @@ mod test{
     #[test]
     fn predecessor_after_del() -> Result<(), String> {
         let mut test_trie = init();
-        test_trie.delete_key(18);
-        if let Some(predecessor) = test_trie.find_predecessor(18) {
-            if predecessor.key == 11 {
+        test_trie.remove(18);
+        if let Some((key, _)) = test_trie.predecessor(18) {
+            if key == 11 {
                 return Ok(());
             }
         }
@@ -837,11 +1170,205 @@ mod test{
     #[test]
     fn deleting_non_existent() -> Result<(), String> {
         let mut test_trie = init();
-        if test_trie.delete_key(19).is_none() {
+        if test_trie.remove(19).is_none() {
             Ok(())
         }
         else {
             Err(String::from("The deleted node didn't exist!!"))
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn remove_returns_value() -> Result<(), String> {
+        let mut test_trie = init();
+        if test_trie.remove(5) == Some(String::from("five")) && test_trie.remove(5).is_none() {
+            Ok(())
+        }
+        else {
+            Err(String::from("Removing 5 should hand back its value once"))
+        }
+    }
+
+    #[test]
+    fn insert_replaces_value() -> Result<(), String> {
+        let mut test_trie = init();
+        let previous = test_trie.insert(11, String::from("xi"));
+        let expected = String::from("xi");
+        if previous == Some(String::from("eleven")) && test_trie.len() == 4
+            && test_trie.successor(6) == Some((11, &expected)) && test_trie.predecessor(17) == Some((11, &expected)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Reinserting 11 should replace its value in place"))
+        }
+    }
+
+    #[test]
+    fn drop_frees_values() -> Result<(), String> {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut test_trie: Xfast<usize, Rc<()>> = Xfast::new(31);
+        for key in 0..20 {
+            test_trie.insert(key, Rc::clone(&value));
+        }
+        test_trie.insert(3, Rc::clone(&value));
+        test_trie.remove(7);
+        let count = Rc::strong_count(&value);
+        drop(test_trie);
+        if count == 20 && Rc::strong_count(&value) == 1 {
+            Ok(())
+        }
+        else {
+            Err(String::from("Dropping the trie should drop every value"))
+        }
+    }
+
+    #[test]
+    fn sorted_iteration() -> Result<(), String> {
+        let mut test_trie = init();
+        test_trie.insert(0, String::from("zero"));
+        test_trie.remove(11);
+        let mut iter = test_trie.iter();
+        let len = iter.len();
+        let last = iter.next_back().map(|(key, _)| key);
+        let keys: Vec<usize> = iter.map(|(key, _)| key).collect();
+        if len == 4 && last == Some(18) && keys == vec![0, 1, 5] {
+            Ok(())
+        }
+        else {
+            Err(format!("Keys should be in ascending order, got {:?}", keys))
+        }
+    }
+
+    #[test]
+    fn sorted_mutable_iteration() -> Result<(), String> {
+        let mut test_trie = init();
+        for (key, value) in test_trie.iter_mut().rev().take(2) {
+            *value = key.to_string();
+        }
+        let values: Vec<&str> = test_trie.iter().map(|(_, value)| value.as_str()).collect();
+        if values == vec!["one", "five", "11", "18"] {
+            Ok(())
+        }
+        else {
+            Err(format!("The largest keys should be updated, got {:?}", values))
+        }
+    }
+
+    #[test]
+    fn min_max_tracking() -> Result<(), String> {
+        let mut test_trie = init();
+        let first = test_trie.pop_first().map(|(key, _)| key);
+        let last = test_trie.pop_last().map(|(key, _)| key);
+        test_trie.insert(20, String::from("twenty"));
+        test_trie.remove(5);
+        let ends = (test_trie.first_key_value().map(|(key, _)| key), test_trie.last_key_value().map(|(key, _)| key));
+        if first == Some(1) && last == Some(18) && ends == (Some(11), Some(20)) {
+            Ok(())
+        }
+        else {
+            Err(format!("Smallest and largest keys are wrong, got {:?} {:?} {:?}", first, last, ends))
+        }
+    }
+
+    #[test]
+    fn pop_until_empty() -> Result<(), String> {
+        let mut test_trie = init();
+        let mut keys = vec![];
+        while let Some((key, _)) = test_trie.pop_last() {
+            keys.push(key);
+        }
+        if keys == vec![18, 11, 5, 1] && test_trie.first_key_value().is_none() && test_trie.iter().next().is_none() {
+            Ok(())
+        }
+        else {
+            Err(format!("Keys should be popped in descending order, got {:?}", keys))
+        }
+    }
+
+    #[test]
+    fn signed_keys() -> Result<(), String> {
+        let mut test_trie: Xfast<i64, &str> = Xfast::default();
+        test_trie.insert(-40, "minus forty");
+        test_trie.insert(i64::MIN, "min");
+        test_trie.insert(7, "seven");
+        test_trie.insert(i64::MAX, "max");
+        if test_trie.successor(-39) == Some((7, &"seven")) && test_trie.predecessor(0) == Some((-40, &"minus forty"))
+            && test_trie.predecessor(i64::MIN + 1) == Some((i64::MIN, &"min")) && test_trie.successor(8) == Some((i64::MAX, &"max")) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Signed keys should be ordered"))
+        }
+    }
+
+    #[test]
+    fn full_width_keys() -> Result<(), String> {
+        let mut test_trie: Xfast<u128, u128> = Xfast::default();
+        for &key in [0, 1, u128::MAX, u128::MAX - 1, 1 << 100].iter() {
+            test_trie.insert(key, key);
+        }
+        test_trie.remove(u128::MAX - 1);
+        if test_trie.successor(2) == Some((1 << 100, &(1 << 100))) && test_trie.successor(u128::MAX - 1) == Some((u128::MAX, &u128::MAX))
+            && test_trie.predecessor(u128::MAX - 1) == Some((1 << 100, &(1 << 100))) {
+            Ok(())
+        }
+        else {
+            Err(String::from("128 bit keys should span the whole trie"))
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn key_out_of_range() {
+        let mut test_trie: Xfast<u32, ()> = Xfast::new(31);
+        test_trie.insert(32, ());
+    }
+
+    #[test]
+    fn freed_nodes_are_reused() -> Result<(), String> {
+        let mut test_trie = init();
+        let allocated = test_trie.nodes.capacity();
+        test_trie.remove(18);
+        test_trie.insert(18, String::from("eighteen"));
+        if test_trie.nodes.capacity() == allocated && test_trie.successor(12).map(|(key, _)| key) == Some(18) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Deleted nodes should have been recycled"))
+        }
+    }
+
+    #[test]
+    fn descendant_ptrs_follow_updates() -> Result<(), String> {
+        let mut test_trie: Xfast<u16, u16> = Xfast::new(1023);
+        let mut key: u16 = 1;
+        let mut broken = Vec::new();
+        for round in 0..3000 {
+            key = key.wrapping_mul(75).wrapping_add(74) % 1024;
+            if round % 3 == 2 {
+                test_trie.remove(key);
+            }
+            else {
+                test_trie.insert(key, key);
+            }
+            // a descendant ptr refers to the smallest or largest leaf of the subtree of its node
+            for (level, prefix, node) in test_trie.levels.entries().into_iter().filter(|entry| entry.0 < 10) {
+                let (start, end) = (prefix << (10 - level), ((prefix + 1) << (10 - level)) - 1);
+                let trie_node = &test_trie.nodes[node];
+                let smallest = test_trie.successor_node(start).filter(|&leaf| test_trie.nodes[leaf].key <= end);
+                let largest = test_trie.predecessor_node(end).filter(|&leaf| test_trie.nodes[leaf].key >= start);
+                if (trie_node.is_desc_left && trie_node.left != smallest) || (trie_node.is_desc_right && trie_node.right != largest) {
+                    broken.push((round, level, prefix));
+                }
+            }
+        }
+        if broken.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(format!("Descendant ptrs should refer to the ends of their subtree, got {:?}", &broken[..broken.len().min(10)]))
+        }
+    }
+}
@@ -0,0 +1,485 @@
+//! Persistent X-fast trie whose versions share their structure.
+//!
+//! The level maps are persistent hash array mapped tries, and the internal nodes refer to their
+//! children and to the leaves of their descendant ptrs by key instead of by address. An update
+//! only copies the internal nodes along the path of its key, the leaves next to it and the map
+//! nodes leading to them, while every other node stays shared with the previous version.
+
+use std::fmt;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Arc;
+
+use crate::hamt::Hamt;
+use crate::{Xfast, XfastKey};
+
+// what an internal node holds on either side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Link {
+    // the child prefixed by the prefix of the node and the bit of the side is present
+    Child,
+    // no child on this side, so a descendant ptr to the nearest leaf on the other side
+    Leaf(u128),
+    // only the root of an empty trie has no leaves below it
+    Empty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Branch {
+    // left and right sides of the node
+    links: [Link; 2],
+}
+
+#[derive(Debug)]
+struct Leaf<V> {
+    value: Arc<V>,
+    // keys of the neighbouring leaves
+    prev: Option<u128>,
+    next: Option<u128>,
+}
+
+impl<V> Clone for Leaf<V> {
+    fn clone(&self) -> Self {
+        Leaf { value: Arc::clone(&self.value), prev: self.prev, next: self.next }
+    }
+}
+
+/// A bitwise trie whose updates return a new version sharing most of its nodes with the old one.
+///
+/// [`insert`](PersistentXfast::insert) and [`remove`](PersistentXfast::remove) leave the trie
+/// they are called on as it was, and copy O(log U) nodes into the version they return. Cloning a
+/// version, or taking a [`snapshot`](PersistentXfast::snapshot) of it, takes constant time, and
+/// versions can be sent to and shared between threads. Queries run in O(log log U) map lookups
+/// like on an [`Xfast`], where every lookup walks O(log n) nodes of a hash array mapped trie.
+/// # Examples
+/// ```
+///     use xfast::PersistentXfast;
+///
+///     let empty: PersistentXfast<u32, &str> = PersistentXfast::new(31);
+///     let first = empty.insert(11, "eleven").insert(1, "one");
+///     let second = first.insert(5, "five").remove(11);
+///
+///     assert_eq!(first.successor(3), Some((11, &"eleven")));
+///     assert_eq!(second.successor(3), Some((5, &"five")));
+///     assert!(empty.is_empty());
+/// ```
+pub struct PersistentXfast<K, V> {
+    nr_levels: usize,
+    // internal nodes of every level above the leaves keyed by their prefix
+    levels: Arc<Vec<Hamt<Branch>>>,
+    // leaves keyed by their key
+    leaves: Hamt<Leaf<V>>,
+    // smallest and largest keys
+    first: Option<u128>,
+    last: Option<u128>,
+    key_type: PhantomData<K>,
+}
+
+impl<K, V> Clone for PersistentXfast<K, V> {
+    // the copy shares every node with the trie
+    fn clone(&self) -> Self {
+        PersistentXfast {
+            nr_levels: self.nr_levels,
+            levels: Arc::clone(&self.levels),
+            leaves: self.leaves.clone(),
+            first: self.first,
+            last: self.last,
+            key_type: PhantomData,
+        }
+    }
+}
+
+impl<K: XfastKey, V> PersistentXfast<K, V> {
+
+    /// Creates a new persistent trie to store keys from zero up to `range`
+    pub fn new(range: K) -> Self {
+        Self::with_levels(Xfast::<K, V>::get_levels_count(range.to_bits()))
+    }
+
+    fn with_levels(nr_levels: usize) -> Self {
+        let mut levels: Vec<Hamt<Branch>> = (0..nr_levels).map(|_| Hamt::new()).collect();
+        // the root at level 0 is always present
+        levels[0].insert(0, Branch { links: [Link::Empty; 2] });
+        PersistentXfast {
+            nr_levels,
+            levels: Arc::new(levels),
+            leaves: Hamt::new(),
+            first: None,
+            last: None,
+            key_type: PhantomData,
+        }
+    }
+
+    // prefix of `key` identifying its ancestor at `level`
+    fn prefix(&self, key: u128, level: usize) -> u128 {
+        key.checked_shr((self.nr_levels - level) as u32).unwrap_or(0)
+    }
+
+    // side of the node at `level` on which `key` lies
+    fn side(&self, key: u128, level: usize) -> usize {
+        ((key >> (self.nr_levels - level - 1)) & 1) as usize
+    }
+
+    fn max_key(&self) -> u128 {
+        u128::MAX >> (128 - self.nr_levels)
+    }
+
+    /// Returns a version of the trie which later updates won't affect, in constant time.
+    pub fn snapshot(&self) -> Self {
+        self.clone()
+    }
+
+    /// Returns the count of values stored in the trie
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Returns true if the trie stores no values
+    pub fn is_empty(&self) -> bool {
+        self.leaves.len() == 0
+    }
+
+    // level of the lowest node which is an ancestor of `key`, or its leaf
+    fn lowest_common_ancestor(&self, key: u128) -> usize {
+        let (mut low, mut high) = (0, self.nr_levels);
+        // the root is an ancestor of every key in range
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            let present = if mid == self.nr_levels {
+                self.leaves.contains_key(key)
+            }
+            else {
+                self.levels[mid].contains_key(self.prefix(key, mid))
+            };
+            if present {
+                low = mid;
+            }
+            else {
+                high = mid - 1;
+            }
+        }
+        low
+    }
+
+    // descendant ptr of the lowest ancestor of `key`, or `key` itself if it is present
+    fn nearest_leaf(&self, key: u128) -> Option<u128> {
+        let level = self.lowest_common_ancestor(key);
+        if level == self.nr_levels {
+            return Some(key);
+        }
+        let branch = self.levels[level].get(self.prefix(key, level)).expect("ancestor of the key");
+        match branch.links[self.side(key, level)] {
+            Link::Leaf(leaf) => Some(leaf),
+            Link::Child => unreachable!("the lowest ancestor has no child towards the key"),
+            Link::Empty => None,
+        }
+    }
+
+    fn leaf(&self, key: u128) -> &Leaf<V> {
+        self.leaves.get(key).expect("key of a leaf")
+    }
+
+    // smallest key >= `key`
+    fn successor_key(&self, key: u128) -> Option<u128> {
+        if key > self.max_key() {
+            return None;
+        }
+        let leaf = self.nearest_leaf(key)?;
+        if leaf < key { self.leaf(leaf).next } else { Some(leaf) }
+    }
+
+    // largest key <= `key`
+    fn predecessor_key(&self, key: u128) -> Option<u128> {
+        let key = key.min(self.max_key());
+        let leaf = self.nearest_leaf(key)?;
+        if leaf > key { self.leaf(leaf).prev } else { Some(leaf) }
+    }
+
+    fn key_value(&self, key: u128) -> (K, &V) {
+        (K::from_bits(key), &self.leaf(key).value)
+    }
+
+    /// Returns a reference to the value stored with `key`
+    pub fn get(&self, key: K) -> Option<&V> {
+        self.leaves.get(key.to_bits()).map(|leaf| &*leaf.value)
+    }
+
+    /// Returns true if the trie stores a value for `key`
+    pub fn contains_key(&self, key: K) -> bool {
+        self.leaves.contains_key(key.to_bits())
+    }
+
+    /// Returns the smallest key more than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn successor(&self, key: K) -> Option<(K, &V)> {
+        self.successor_key(key.to_bits()).map(|found| self.key_value(found))
+    }
+
+    /// Returns the largest key less than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn predecessor(&self, key: K) -> Option<(K, &V)> {
+        self.predecessor_key(key.to_bits()).map(|found| self.key_value(found))
+    }
+
+    /// Returns the smallest key of the trie along with its value
+    pub fn first_key_value(&self) -> Option<(K, &V)> {
+        self.first.map(|key| self.key_value(key))
+    }
+
+    /// Returns the largest key of the trie along with its value
+    pub fn last_key_value(&self) -> Option<(K, &V)> {
+        self.last.map(|key| self.key_value(key))
+    }
+
+    /// Returns a version of the trie with `key` and `value` inserted. If the key was already
+    /// present its value is replaced in the returned version.
+    /// # Examples
+    /// ```
+    ///     use xfast::PersistentXfast;
+    ///
+    ///     let first: PersistentXfast<u32, &str> = PersistentXfast::new(31).insert(11, "eleven");
+    ///     let second = first.insert(11, "xi");
+    ///     assert_eq!(first.get(11), Some(&"eleven"));
+    ///     assert_eq!(second.get(11), Some(&"xi"));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `key` is larger than the range the trie was created with.
+    pub fn insert(&self, key: K, value: V) -> Self {
+        let mut version = self.clone();
+        version.insert_in_place(key.to_bits(), value);
+        version
+    }
+
+    /// Returns a version of the trie without `key`.
+    pub fn remove(&self, key: K) -> Self {
+        let mut version = self.clone();
+        version.remove_in_place(key.to_bits());
+        version
+    }
+
+    fn insert_in_place(&mut self, key: u128, value: V) {
+        assert!(key <= self.max_key(), "key out of the range of the trie");
+        if let Some(leaf) = self.leaves.get_mut(key) {
+            leaf.value = Arc::new(value);
+            return;
+        }
+
+        // link the leaf between its neighbours
+        let prev = self.predecessor_key(key);
+        let next = self.successor_key(key);
+        self.leaves.insert(key, Leaf { value: Arc::new(value), prev, next });
+        match prev {
+            Some(prev) => self.leaves.get_mut(prev).expect("key of a leaf").next = Some(key),
+            None => self.first = Some(key),
+        }
+        match next {
+            Some(next) => self.leaves.get_mut(next).expect("key of a leaf").prev = Some(key),
+            None => self.last = Some(key),
+        }
+
+        // every ancestor gets a child towards the key, and the descendant ptr on its other side
+        // may now lead to the new leaf
+        let mut levels = mem::take(Arc::make_mut(&mut self.levels));
+        for (level, level_map) in levels.iter_mut().enumerate() {
+            let prefix = self.prefix(key, level);
+            let side = self.side(key, level);
+            let original = level_map.get(prefix).copied();
+            let mut branch = original.unwrap_or(Branch { links: [Link::Empty; 2] });
+            branch.links[side] = Link::Child;
+            branch.links[1 - side] = match branch.links[1 - side] {
+                Link::Child => Link::Child,
+                Link::Leaf(leaf) if side == 1 => Link::Leaf(leaf.min(key)),
+                Link::Leaf(leaf) => Link::Leaf(leaf.max(key)),
+                Link::Empty => Link::Leaf(key),
+            };
+            if original != Some(branch) {
+                level_map.insert(prefix, branch);
+            }
+        }
+        *Arc::make_mut(&mut self.levels) = levels;
+    }
+
+    fn remove_in_place(&mut self, key: u128) {
+        let leaf = match self.leaves.remove(key) {
+            Some(leaf) => leaf,
+            None => return,
+        };
+        match leaf.prev {
+            Some(prev) => self.leaves.get_mut(prev).expect("key of a leaf").next = leaf.next,
+            None => self.first = leaf.next,
+        }
+        match leaf.next {
+            Some(next) => self.leaves.get_mut(next).expect("key of a leaf").prev = leaf.prev,
+            None => self.last = leaf.prev,
+        }
+
+        // walk up dropping the ancestors left without leaves, then point the descendant ptrs
+        // which led to the removed leaf at its neighbours
+        let mut levels = mem::take(Arc::make_mut(&mut self.levels));
+        let mut child_removed = true;
+        for level in (0..self.nr_levels).rev() {
+            let prefix = self.prefix(key, level);
+            let side = self.side(key, level);
+            let original = *levels[level].get(prefix).expect("ancestor of a leaf");
+            let mut branch = original;
+            // the nearest leaf on the left side of a node is its smallest one and vice versa
+            let neighbour = |side: usize| if side == 0 { leaf.next } else { leaf.prev };
+            if child_removed {
+                if branch.links[1 - side] != Link::Child {
+                    if level > 0 {
+                        levels[level].remove(prefix);
+                        continue;
+                    }
+                    branch.links = [Link::Empty; 2];
+                }
+                else {
+                    branch.links[side] = Link::Leaf(neighbour(side).expect("leaf on the other side"));
+                    child_removed = false;
+                }
+            }
+            else if branch.links[1 - side] == Link::Leaf(key) {
+                branch.links[1 - side] = Link::Leaf(neighbour(1 - side).expect("leaf below the node"));
+            }
+            if branch != original {
+                levels[level].insert(prefix, branch);
+            }
+        }
+        *Arc::make_mut(&mut self.levels) = levels;
+    }
+
+    /// Returns an iterator around all the key-value pairs stored in the trie, in ascending order of keys.
+    pub fn iter(&self) -> PersistentXfastIter<'_, K, V> {
+        PersistentXfastIter {
+            trie: self,
+            front: self.first,
+            back: self.last,
+            remaining: self.len(),
+        }
+    }
+}
+
+impl<K: XfastKey, V> Default for PersistentXfast<K, V> {
+    /// Creates an empty trie spanning the full width of the key type
+    fn default() -> Self {
+        Self::with_levels(K::BITS)
+    }
+}
+
+impl<K: XfastKey + fmt::Debug, V: fmt::Debug> fmt::Debug for PersistentXfast<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator around the PersistentXfast key and value pairs in ascending order of keys
+pub struct PersistentXfastIter<'a, K, V> {
+    trie: &'a PersistentXfast<K, V>,
+    // keys of the next leaves from either end
+    front: Option<u128>,
+    back: Option<u128>,
+    remaining: usize,
+}
+
+impl<'a, K: XfastKey, V> Iterator for PersistentXfastIter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let key = self.front?;
+        self.front = self.trie.leaf(key).next;
+        self.remaining -= 1;
+        Some(self.trie.key_value(key))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: XfastKey, V> DoubleEndedIterator for PersistentXfastIter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let key = self.back?;
+        self.back = self.trie.leaf(key).prev;
+        self.remaining -= 1;
+        Some(self.trie.key_value(key))
+    }
+}
+
+impl<'a, K: XfastKey, V> ExactSizeIterator for PersistentXfastIter<'a, K, V> {}
+
+impl<'a, K: XfastKey, V> FusedIterator for PersistentXfastIter<'a, K, V> {}
+
+impl<'a, K: XfastKey, V> IntoIterator for &'a PersistentXfast<K, V> {
+    type Item = (K, &'a V);
+    type IntoIter = PersistentXfastIter<'a, K, V>;
+    fn into_iter(self) -> PersistentXfastIter<'a, K, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::PersistentXfast;
+    use crate::Xfast;
+
+    fn init() -> PersistentXfast<u16, u16> {
+        (0..1024).filter(|key| key % 3 == 0).fold(PersistentXfast::new(1023), |trie, key| trie.insert(key, key))
+    }
+
+    #[test]
+    fn versions_are_independent() -> Result<(), String> {
+        let full = init();
+        let halved = (0..1024).filter(|key| key % 6 == 0).fold(full.snapshot(), |trie, key| trie.remove(key));
+        let replaced = halved.insert(3, 0);
+        if full.len() == 342 && halved.len() == 171 && full.get(6) == Some(&6) && halved.get(6).is_none()
+            && halved.get(3) == Some(&3) && replaced.get(3) == Some(&0) && full.iter().map(|(key, _)| key).eq((0..1024).step_by(3)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Updating a version should leave the others as they were"))
+        }
+    }
+
+    #[test]
+    fn same_neighbours_as_xfast() -> Result<(), String> {
+        let mut version: PersistentXfast<u16, u16> = PersistentXfast::new(255);
+        let mut expected: Xfast<u16, u16> = Xfast::new(255);
+        let mut key: u16 = 1;
+        for round in 0..600 {
+            key = (key * 73 + 19) % 256;
+            if round % 3 == 2 {
+                version = version.remove(key);
+                expected.remove(key);
+            }
+            else {
+                version = version.insert(key, round);
+                expected.insert(key, round);
+            }
+            let same = (0..256).all(|query| version.successor(query) == expected.successor(query)
+                && version.predecessor(query) == expected.predecessor(query));
+            if !same || !version.iter().rev().eq(expected.iter().rev()) {
+                return Err(format!("Neighbours differ after round {}", round));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots_across_threads() -> Result<(), String> {
+        fn shareable<T: Send + Sync>(_: &T) {}
+        let snapshot = init().snapshot();
+        shareable(&snapshot);
+        let reader = thread::spawn(move || snapshot.successor(1000).map(|(key, &value)| (key, value)));
+        match reader.join() {
+            Ok(Some((1002, 1002))) => Ok(()),
+            other => Err(format!("Snapshot read on another thread is wrong, got {:?}", other)),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Ordered iteration over the leaves of the trie with keys in a given range.
+
+use std::hash::BuildHasher;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+
+use crate::arena::{Arena, LeafValues, RawLeafValues};
+use crate::{Node, TrieNode, Xfast, XfastKey};
+
+/// Double-ended iterator around the Xfast key and value pairs with keys in a range
+pub struct XfastRange<'a, K, V> {
+    nodes: &'a Arena<TrieNode>,
+    values: &'a LeafValues<V>,
+    // next leaves to be yielded from either end, both None once the ends have met
+    front: Option<Node>,
+    back: Option<Node>,
+    key_type: PhantomData<K>,
+}
+
+/// Double-ended iterator around the Xfast keys in a range and their mutable values
+pub struct XfastRangeMut<'a, K, V> {
+    nodes: &'a Arena<TrieNode>,
+    values: RawLeafValues<V>,
+    front: Option<Node>,
+    back: Option<Node>,
+    trie_type: PhantomData<(K, &'a mut V)>,
+}
+
+impl<K: XfastKey, V, S: BuildHasher> Xfast<K, V, S> {
+
+    // first and last leaves with keys in `range`, None if no key falls in it
+    pub(crate) fn leaf_bounds<R: RangeBounds<K>>(&self, range: R) -> Option<(Node, Node)> {
+        let front = match range.start_bound() {
+            Bound::Included(start) => self.successor_node(start.to_bits())?,
+            Bound::Excluded(start) => {
+                let node = self.successor_node(start.to_bits())?;
+                // skip the start itself when it's present in the trie
+                if self.nodes[node].key == start.to_bits() { self.nodes[node].right? } else { node }
+            }
+            Bound::Unbounded => self.first_leaf()?,
+        };
+        let back = match range.end_bound() {
+            Bound::Included(end) => self.predecessor_node(end.to_bits())?,
+            Bound::Excluded(end) => {
+                let node = self.predecessor_node(end.to_bits())?;
+                if self.nodes[node].key == end.to_bits() { self.nodes[node].left? } else { node }
+            }
+            Bound::Unbounded => self.last_leaf()?,
+        };
+
+        // an empty range leaves its ends crossed
+        if self.nodes[front].key > self.nodes[back].key {
+            return None;
+        }
+        Some((front, back))
+    }
+}
+
+impl<'a, K, V> XfastRange<'a, K, V> {
+    pub(crate) fn new(nodes: &'a Arena<TrieNode>, values: &'a LeafValues<V>, bounds: Option<(Node, Node)>) -> Self {
+        XfastRange {
+            nodes,
+            values,
+            front: bounds.map(|(front, _)| front),
+            back: bounds.map(|(_, back)| back),
+            key_type: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V> Iterator for XfastRange<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.front?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        }
+        else {
+            self.front = self.nodes[node].right;
+        }
+        Some((K::from_bits(self.nodes[node].key), self.values.get(node)))
+    }
+}
+
+impl<'a, K: XfastKey, V> DoubleEndedIterator for XfastRange<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.back?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        }
+        else {
+            self.back = self.nodes[node].left;
+        }
+        Some((K::from_bits(self.nodes[node].key), self.values.get(node)))
+    }
+}
+
+impl<'a, K: XfastKey, V> FusedIterator for XfastRange<'a, K, V> {}
+
+impl<'a, K, V> XfastRangeMut<'a, K, V> {
+    pub(crate) fn new(nodes: &'a Arena<TrieNode>, values: &'a mut LeafValues<V>, bounds: Option<(Node, Node)>) -> Self {
+        XfastRangeMut {
+            nodes,
+            values: values.as_raw(),
+            front: bounds.map(|(front, _)| front),
+            back: bounds.map(|(_, back)| back),
+            trie_type: PhantomData,
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V> Iterator for XfastRangeMut<'a, K, V> {
+    type Item = (K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.front?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        }
+        else {
+            self.front = self.nodes[node].right;
+        }
+        // the trie stays mutably borrowed for 'a and every leaf is handed out at most once
+        let value: &'a mut V = unsafe { self.values.get_mut(node) };
+        Some((K::from_bits(self.nodes[node].key), value))
+    }
+}
+
+impl<'a, K: XfastKey, V> DoubleEndedIterator for XfastRangeMut<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = self.back?;
+        if self.front == self.back {
+            self.front = None;
+            self.back = None;
+        }
+        else {
+            self.back = self.nodes[node].left;
+        }
+        // the trie stays mutably borrowed for 'a and every leaf is handed out at most once
+        let value: &'a mut V = unsafe { self.values.get_mut(node) };
+        Some((K::from_bits(self.nodes[node].key), value))
+    }
+}
+
+impl<'a, K: XfastKey, V> FusedIterator for XfastRangeMut<'a, K, V> {}
+
+#[cfg(test)]
+mod test {
+    use crate::Xfast;
+
+    fn init() -> Xfast<usize, usize> {
+        let mut test_trie = Xfast::new(31);
+        for &key in [11, 1, 18, 5, 30, 0].iter() {
+            test_trie.insert(key, key * 10);
+        }
+        test_trie
+    }
+
+    fn keys<'a>(pairs: impl Iterator<Item = (usize, &'a usize)>) -> Vec<usize> {
+        pairs.map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn bounds() -> Result<(), String> {
+        let test_trie = init();
+        let ranges = vec![
+            keys(test_trie.range(1..18)),
+            keys(test_trie.range(2..=18)),
+            keys(test_trie.range(..5)),
+            keys(test_trie.range(19..)),
+            keys(test_trie.range(..)),
+            keys(test_trie.range(12..18)),
+            keys(test_trie.range(6..=6)),
+        ];
+        let expected = vec![
+            vec![1, 5, 11],
+            vec![5, 11, 18],
+            vec![0, 1],
+            vec![30],
+            vec![0, 1, 5, 11, 18, 30],
+            vec![],
+            vec![],
+        ];
+        if ranges == expected {
+            Ok(())
+        }
+        else {
+            Err(format!("Ranges are wrong, got {:?}", ranges))
+        }
+    }
+
+    #[test]
+    fn excluded_start() -> Result<(), String> {
+        use std::ops::Bound;
+
+        let test_trie = init();
+        let keys = keys(test_trie.range((Bound::Excluded(5), Bound::Excluded(30))));
+        if keys == vec![11, 18] {
+            Ok(())
+        }
+        else {
+            Err(format!("Excluded bounds are wrong, got {:?}", keys))
+        }
+    }
+
+    #[test]
+    fn both_ends() -> Result<(), String> {
+        let test_trie = init();
+        let mut range = test_trie.range(1..=30);
+        let ends = (range.next_back(), range.next(), range.next_back(), range.next(), range.next_back(), range.next());
+        if ends == (Some((30, &300)), Some((1, &10)), Some((18, &180)), Some((5, &50)), Some((11, &110)), None) {
+            Ok(())
+        }
+        else {
+            Err(format!("The ends of the range should meet once, got {:?}", ends))
+        }
+    }
+
+    #[test]
+    fn mutable_range() -> Result<(), String> {
+        let mut test_trie = init();
+        {
+            let mut range = test_trie.range_mut(5..);
+            if let Some((_, value)) = range.next_back() {
+                *value += 1;
+            }
+            for (_, value) in range {
+                *value += 2;
+            }
+        }
+        let values: Vec<usize> = test_trie.range(..).map(|(_, &value)| value).collect();
+        if values == vec![0, 10, 52, 112, 182, 301] {
+            Ok(())
+        }
+        else {
+            Err(format!("Values in the range should be updated once, got {:?}", values))
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Serde support for [`Xfast`] and [`XfastSet`], enabled by the `serde` feature.
+//!
+//! A trie is serialized as the range it was created with followed by its entries in ascending
+//! order of keys, so the nodes are never written out. Deserializing rebuilds the trie bottom-up
+//! from the entries and rejects keys which are out of order, repeated or out of range.
+
+use std::fmt;
+use std::hash::BuildHasher;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{Xfast, XfastKey, XfastSet};
+
+const FIELDS: &[&str] = &["range", "entries"];
+
+// entries of a trie written out as a sequence
+struct Entries<'a, K, V, H>(&'a Xfast<K, V, H>);
+
+impl<'a, K: XfastKey + Serialize, V: Serialize, H: BuildHasher> Serialize for Entries<'a, K, V, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+// keys of a set written out as a sequence
+struct Keys<'a, K, H>(&'a XfastSet<K, H>);
+
+impl<'a, K: XfastKey + Serialize, H: BuildHasher> Serialize for Keys<'a, K, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+impl<K: XfastKey + Serialize, V: Serialize, H: BuildHasher> Serialize for Xfast<K, V, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Xfast", 2)?;
+        state.serialize_field("range", &K::from_bits(self.max_key()))?;
+        state.serialize_field("entries", &Entries(self))?;
+        state.end()
+    }
+}
+
+impl<K: XfastKey + Serialize, H: BuildHasher> Serialize for XfastSet<K, H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("XfastSet", 2)?;
+        state.serialize_field("range", &K::from_bits(self.trie.max_key()))?;
+        state.serialize_field("entries", &Keys(self))?;
+        state.end()
+    }
+}
+
+// range and entries read back before the trie is built
+struct Snapshot<K, E> {
+    range: K,
+    entries: Vec<E>,
+}
+
+enum Field {
+    Range,
+    Entries,
+}
+
+impl<'de> Deserialize<'de> for Field {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FieldVisitor;
+
+        impl<'de> Visitor<'de> for FieldVisitor {
+            type Value = Field;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("`range` or `entries`")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Field, E> {
+                match value {
+                    "range" => Ok(Field::Range),
+                    "entries" => Ok(Field::Entries),
+                    _ => Err(de::Error::unknown_field(value, FIELDS)),
+                }
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldVisitor)
+    }
+}
+
+struct SnapshotVisitor<K, E> {
+    name: &'static str,
+    marker: PhantomData<(K, E)>,
+}
+
+impl<'de, K: Deserialize<'de>, E: Deserialize<'de>> Visitor<'de> for SnapshotVisitor<K, E> {
+    type Value = Snapshot<K, E>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "struct {}", self.name)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let range = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let entries = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(Snapshot { range, entries })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut range = None;
+        let mut entries = None;
+        while let Some(field) = map.next_key()? {
+            match field {
+                Field::Range => {
+                    if range.is_some() {
+                        return Err(de::Error::duplicate_field("range"));
+                    }
+                    range = Some(map.next_value()?);
+                }
+                Field::Entries => {
+                    if entries.is_some() {
+                        return Err(de::Error::duplicate_field("entries"));
+                    }
+                    entries = Some(map.next_value()?);
+                }
+            }
+        }
+        let range = range.ok_or_else(|| de::Error::missing_field("range"))?;
+        let entries = entries.ok_or_else(|| de::Error::missing_field("entries"))?;
+        Ok(Snapshot { range, entries })
+    }
+}
+
+impl<'de, K: Deserialize<'de>, E: Deserialize<'de>> Snapshot<K, E> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D, name: &'static str) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct(name, FIELDS, SnapshotVisitor { name, marker: PhantomData })
+    }
+}
+
+impl<'de, K, V, H> Deserialize<'de> for Xfast<K, V, H>
+where
+    K: XfastKey + fmt::Debug + Deserialize<'de>,
+    V: Deserialize<'de>,
+    H: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot: Snapshot<K, (K, V)> = Snapshot::deserialize(deserializer, "Xfast")?;
+        Xfast::with_hasher(snapshot.range, H::default()).build_sorted(snapshot.entries, true).map_err(de::Error::custom)
+    }
+}
+
+impl<'de, K, H> Deserialize<'de> for XfastSet<K, H>
+where
+    K: XfastKey + fmt::Debug + Deserialize<'de>,
+    H: BuildHasher + Clone + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot: Snapshot<K, K> = Snapshot::deserialize(deserializer, "XfastSet")?;
+        let entries = snapshot.entries.into_iter().map(|key| (key, ()));
+        let trie = Xfast::with_hasher(snapshot.range, H::default()).build_sorted(entries, true).map_err(de::Error::custom)?;
+        Ok(XfastSet { trie })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Xfast, XfastSet};
+
+    fn init() -> Xfast<u16, String> {
+        let mut test_trie = Xfast::new(1023);
+        for key in (0..1024).filter(|key| key % 7 == 3) {
+            test_trie.insert(key, key.to_string());
+        }
+        test_trie
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), String> {
+        let test_trie = init();
+        let json = serde_json::to_string(&test_trie).map_err(|error| error.to_string())?;
+        let read: Xfast<u16, String> = serde_json::from_str(&json).map_err(|error| error.to_string())?;
+        if read.iter().eq(test_trie.iter()) && read.successor(1021).is_none() && read.predecessor(1023) == Some((1018, &String::from("1018"))) {
+            Ok(())
+        }
+        else {
+            Err(format!("Trie read back is wrong, got {}", json))
+        }
+    }
+
+    #[test]
+    fn set_round_trip() -> Result<(), String> {
+        let mut test_set: XfastSet<i32> = XfastSet::default();
+        for &key in [-40, -1, 0, 7, 1 << 20].iter() {
+            test_set.insert(key);
+        }
+        let json = serde_json::to_string(&test_set).map_err(|error| error.to_string())?;
+        let read: XfastSet<i32> = serde_json::from_str(&json).map_err(|error| error.to_string())?;
+        if read.iter().eq(test_set.iter()) && json.ends_with("\"entries\":[-40,-1,0,7,1048576]}") {
+            Ok(())
+        }
+        else {
+            Err(format!("Set read back is wrong, got {}", json))
+        }
+    }
+
+    #[test]
+    fn rejected_entries() -> Result<(), String> {
+        let errors: Vec<String> = [
+            r#"{"range":31,"entries":[[1,1],[40,2]]}"#,
+            r#"{"range":31,"entries":[[1,1],[1,2]]}"#,
+            r#"{"range":31,"entries":[[5,1],[1,2]]}"#,
+        ].iter().filter_map(|json| serde_json::from_str::<Xfast<u8, u8>>(json).err()).map(|error| error.to_string()).collect();
+        let expected = ["out of the range", "appears more than once", "smaller than the key before it"];
+        if errors.len() == 3 && errors.iter().zip(expected.iter()).all(|(error, expected)| error.contains(expected)) {
+            Ok(())
+        }
+        else {
+            Err(format!("Bad entries should be rejected, got {:?}", errors))
+        }
+    }
+}
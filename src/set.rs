@@ -0,0 +1,403 @@
+//! A set of integers backed by the X-fast trie.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::iter::FusedIterator;
+use std::ops::RangeBounds;
+
+use crate::{Difference, Gaps, Intersection, SymmetricDifference, Union, Xfast, XfastIter, XfastKey};
+
+/// Iterator around the keys present in either of two sets, in ascending order
+pub type SetUnion<'a, K> = Union<'a, K, (), ()>;
+
+/// Iterator around the keys present in both of two sets, in ascending order
+pub type SetIntersection<'a, K, S = RandomState> = Intersection<'a, K, (), (), S>;
+
+/// Iterator around the keys present in one set and absent from another, in ascending order
+pub type SetDifference<'a, K, S = RandomState> = Difference<'a, K, (), (), S>;
+
+/// Iterator around the keys present in exactly one of two sets, in ascending order
+pub type SetSymmetricDifference<'a, K> = SymmetricDifference<'a, K, (), ()>;
+
+/// Iterator around the ranges of keys absent from a set within some bounds, in ascending order
+pub type SetGaps<'a, K> = Gaps<'a, K, ()>;
+
+/// A set of integers stored in a bitwise trie.
+///
+/// The set shares the level maps and the linked list of leaves with [`Xfast`]. Its values are the
+/// zero sized `()`, so the set stores nothing per key besides the nodes of the trie.
+///
+/// The range of keys can be specified while initializing a set, otherwise the set spans the full
+/// width of the key type.
+/// # Examples
+/// ```
+///     use xfast::XfastSet;
+///
+///     let mut test_set: XfastSet<u32> = XfastSet::new(31);
+///     test_set.insert(11);
+///     test_set.insert(1);
+///     test_set.insert(5);
+///     assert_eq!(test_set.len(), 3);
+///
+///     assert_eq!(test_set.successor(6), Some(11));
+///     assert_eq!(test_set.predecessor(4), Some(1));
+///     assert_eq!(test_set.iter().collect::<Vec<_>>(), vec![1, 5, 11]);
+/// ```
+#[derive(Debug)]
+pub struct XfastSet<K, S = RandomState> {
+    pub(crate) trie: Xfast<K, (), S>,
+}
+
+impl<K: XfastKey> XfastSet<K> {
+
+    /// Creates a new set to store keys from zero up to `range`
+    /// # Examples
+    /// ```
+    /// # #![allow(unused_mut)]
+    ///     use xfast::XfastSet;
+    ///
+    ///     let mut test_set: XfastSet<u32> = XfastSet::new(31);
+    /// ```
+    pub fn new(range: K) -> Self {
+        XfastSet {
+            trie: Xfast::new(range),
+        }
+    }
+}
+
+impl<K: XfastKey, S: BuildHasher + Clone> XfastSet<K, S> {
+
+    /// Creates a new set to store keys from zero up to `range`, hashing the prefixes at every
+    /// level of its trie with `hash_builder`
+    pub fn with_hasher(range: K, hash_builder: S) -> Self {
+        XfastSet {
+            trie: Xfast::with_hasher(range, hash_builder),
+        }
+    }
+
+    /// Creates a new set to store keys from zero up to `range` with room for at least `capacity`
+    /// keys, hashing the prefixes with `hash_builder`
+    pub fn with_capacity_and_hasher(range: K, capacity: usize, hash_builder: S) -> Self {
+        XfastSet {
+            trie: Xfast::with_capacity_and_hasher(range, capacity, hash_builder),
+        }
+    }
+
+    /// Splits the set in two at `key`. Returns a set holding every key larger than or equal to
+    /// `key`, while the smaller keys are left in `self`.
+    pub fn split_off(&mut self, key: K) -> Self {
+        XfastSet {
+            trie: self.trie.split_off(key),
+        }
+    }
+
+    /// Moves every key of `other` into `self`, leaving `other` empty.
+    pub fn append(&mut self, other: &mut XfastSet<K, S>) {
+        self.trie.append(&mut other.trie)
+    }
+}
+
+impl<K: XfastKey, S: BuildHasher> XfastSet<K, S> {
+
+    /// Returns the hasher building the hashes of the prefixes in the trie of the set
+    pub fn hasher(&self) -> &S {
+        self.trie.hasher()
+    }
+
+    /// Returns the count of keys stored in the set
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    /// Returns true if the set stores no keys
+    pub fn is_empty(&self) -> bool {
+        self.trie.is_empty()
+    }
+
+    /// Adds `key` to the set. Returns false if the key was already present.
+    /// # Examples
+    /// ```
+    ///     use xfast::XfastSet;
+    ///
+    ///     let mut test_set: XfastSet<u32> = XfastSet::new(31);
+    ///     assert!(test_set.insert(11));
+    ///     assert!(!test_set.insert(11));
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `key` is larger than the range the set was created with.
+    pub fn insert(&mut self, key: K) -> bool {
+        self.trie.insert(key, ()).is_none()
+    }
+
+    /// Removes `key` from the set. Returns false if the key wasn't present.
+    /// # Examples
+    /// ```
+    ///     use xfast::XfastSet;
+    ///
+    ///     let mut test_set: XfastSet<u32> = XfastSet::new(31);
+    ///     test_set.insert(11);
+    ///     assert!(test_set.remove(11));
+    ///     assert!(!test_set.remove(11));
+    /// ```
+    pub fn remove(&mut self, key: K) -> bool {
+        self.trie.remove(key).is_some()
+    }
+
+    /// Returns true if `key` is present in the set
+    pub fn contains(&self, key: K) -> bool {
+        self.trie.contains_key(key)
+    }
+
+    /// Returns the smallest key more than or equal to `key`. In case of no such key it returns None.
+    pub fn successor(&self, key: K) -> Option<K> {
+        self.trie.successor(key).map(|(key, _)| key)
+    }
+
+    /// Returns the largest key less than or equal to `key`. In case of no such key it returns None.
+    pub fn predecessor(&self, key: K) -> Option<K> {
+        self.trie.predecessor(key).map(|(key, _)| key)
+    }
+
+    /// Returns the smallest key of the set
+    pub fn first(&self) -> Option<K> {
+        self.trie.first_key_value().map(|(key, _)| key)
+    }
+
+    /// Returns the largest key of the set
+    pub fn last(&self) -> Option<K> {
+        self.trie.last_key_value().map(|(key, _)| key)
+    }
+
+    /// Removes the smallest key from the set and returns it.
+    /// # Examples
+    /// ```
+    ///     use xfast::XfastSet;
+    ///
+    ///     let mut test_set: XfastSet<u32> = XfastSet::new(31);
+    ///     test_set.insert(19);
+    ///     test_set.insert(3);
+    ///     assert_eq!(test_set.pop_first(), Some(3));
+    ///     assert_eq!(test_set.first(), Some(19));
+    /// ```
+    pub fn pop_first(&mut self) -> Option<K> {
+        self.trie.pop_first().map(|(key, _)| key)
+    }
+
+    /// Removes the largest key from the set and returns it.
+    pub fn pop_last(&mut self) -> Option<K> {
+        self.trie.pop_last().map(|(key, _)| key)
+    }
+
+    /// Returns an iterator around the keys of the set in ascending order.
+    /// # Examples
+    /// ```
+    ///     use xfast::XfastSet;
+    ///
+    ///     let mut test_set: XfastSet<u32> = XfastSet::new(31);
+    ///     test_set.insert(19);
+    ///     test_set.insert(3);
+    ///     test_set.insert(7);
+    ///     assert_eq!(test_set.iter().collect::<Vec<_>>(), vec![3, 7, 19]);
+    /// ```
+    pub fn iter(&self) -> XfastSetIter<'_, K> {
+        XfastSetIter {
+            iter: self.trie.iter(),
+        }
+    }
+
+    /// Returns an iterator around the keys present in either `self` or `other`.
+    /// # Examples
+    /// ```
+    ///     use xfast::XfastSet;
+    ///
+    ///     let mut odd: XfastSet<u32> = XfastSet::new(31);
+    ///     let mut squares: XfastSet<u32> = XfastSet::new(31);
+    ///     for &key in [1, 3, 5].iter() {
+    ///         odd.insert(key);
+    ///     }
+    ///     for &key in [1, 4].iter() {
+    ///         squares.insert(key);
+    ///     }
+    ///     assert_eq!(odd.union(&squares).collect::<Vec<_>>(), vec![1, 3, 4, 5]);
+    ///     assert_eq!(odd.intersection(&squares).collect::<Vec<_>>(), vec![1]);
+    ///     assert_eq!(odd.difference(&squares).collect::<Vec<_>>(), vec![3, 5]);
+    ///     assert_eq!(odd.symmetric_difference(&squares).collect::<Vec<_>>(), vec![3, 4, 5]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the sets weren't created with the same range.
+    pub fn union<'a>(&'a self, other: &'a XfastSet<K, S>) -> SetUnion<'a, K> {
+        self.trie.union(&other.trie)
+    }
+
+    /// Returns an iterator around the keys present in both `self` and `other`.
+    pub fn intersection<'a>(&'a self, other: &'a XfastSet<K, S>) -> SetIntersection<'a, K, S> {
+        self.trie.intersection(&other.trie)
+    }
+
+    /// Returns an iterator around the keys present in `self` and absent from `other`.
+    pub fn difference<'a>(&'a self, other: &'a XfastSet<K, S>) -> SetDifference<'a, K, S> {
+        self.trie.difference(&other.trie)
+    }
+
+    /// Returns an iterator around the keys present in exactly one of `self` and `other`.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a XfastSet<K, S>) -> SetSymmetricDifference<'a, K> {
+        self.trie.symmetric_difference(&other.trie)
+    }
+
+    /// Removes the keys absent from `other`, keeping the intersection of the sets in `self`.
+    pub fn retain_in(&mut self, other: &XfastSet<K, S>) {
+        self.trie.retain_in(&other.trie)
+    }
+
+    /// Inserts the keys of `other`, keeping the union of the sets in `self`.
+    pub fn extend_from(&mut self, other: &XfastSet<K, S>) {
+        self.trie.extend_from(&other.trie)
+    }
+
+    /// Returns an iterator around the ranges of keys within `bounds` which are absent from the set.
+    /// # Examples
+    /// ```
+    ///     use xfast::XfastSet;
+    ///
+    ///     let mut test_set: XfastSet<u32> = XfastSet::new(31);
+    ///     test_set.insert(3);
+    ///     test_set.insert(9);
+    ///     assert_eq!(test_set.complement_within(..=10).collect::<Vec<_>>(), vec![0..=2, 4..=8, 10..=10]);
+    /// ```
+    pub fn complement_within<R: RangeBounds<K>>(&self, bounds: R) -> SetGaps<'_, K> {
+        self.trie.complement_within(bounds)
+    }
+}
+
+impl<K: XfastKey, S: BuildHasher + Clone + Default> Default for XfastSet<K, S> {
+    /// Creates an empty set spanning the full width of the key type
+    fn default() -> Self {
+        XfastSet {
+            trie: Xfast::default(),
+        }
+    }
+}
+
+/// Iterator around the keys of a `XfastSet` in ascending order
+pub struct XfastSetIter<'a, K> {
+    iter: XfastIter<'a, K, ()>,
+}
+
+impl<'a, K: XfastKey> Iterator for XfastSetIter<'a, K> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, K: XfastKey> DoubleEndedIterator for XfastSetIter<'a, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<'a, K: XfastKey> ExactSizeIterator for XfastSetIter<'a, K> {}
+
+impl<'a, K: XfastKey> FusedIterator for XfastSetIter<'a, K> {}
+
+impl<'a, K: XfastKey, S: BuildHasher> IntoIterator for &'a XfastSet<K, S> {
+    type Item = K;
+    type IntoIter = XfastSetIter<'a, K>;
+    fn into_iter(self) -> XfastSetIter<'a, K> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SetDifference, SetSymmetricDifference, SetUnion, XfastSet};
+
+    fn init() -> XfastSet<usize> {
+        let mut test_set = XfastSet::new(31);
+        for &key in [11, 1, 18, 5].iter() {
+            test_set.insert(key);
+        }
+        test_set
+    }
+
+    #[test]
+    fn insert_and_remove() -> Result<(), String> {
+        let mut test_set = init();
+        if !test_set.insert(7) || test_set.insert(7) || !test_set.remove(18) || test_set.remove(18) {
+            return Err(String::from("insert and remove should report membership changes"));
+        }
+        if test_set.len() == 4 && test_set.contains(7) && !test_set.contains(18) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Set membership is wrong"))
+        }
+    }
+
+    #[test]
+    fn neighbours() -> Result<(), String> {
+        let test_set = init();
+        if test_set.successor(12) == Some(18) && test_set.predecessor(10) == Some(5)
+            && test_set.successor(19).is_none() && test_set.predecessor(0).is_none() {
+            Ok(())
+        }
+        else {
+            Err(String::from("Successor or predecessor is wrong"))
+        }
+    }
+
+    #[test]
+    fn signed_keys() -> Result<(), String> {
+        let mut test_set: XfastSet<i16> = XfastSet::default();
+        for &key in [3, -300, i16::MIN, 0, -1].iter() {
+            test_set.insert(key);
+        }
+        let keys: Vec<i16> = test_set.iter().collect();
+        if keys == vec![i16::MIN, -300, -1, 0, 3] && test_set.successor(-299) == Some(-1) {
+            Ok(())
+        }
+        else {
+            Err(format!("Signed keys should be in ascending order, got {:?}", keys))
+        }
+    }
+
+    #[test]
+    fn ordered_iteration() -> Result<(), String> {
+        let mut test_set = init();
+        test_set.remove(5);
+        test_set.insert(0);
+        let keys: Vec<usize> = test_set.iter().collect();
+        if keys == vec![0, 1, 11, 18] {
+            Ok(())
+        }
+        else {
+            Err(format!("Keys should be in ascending order, got {:?}", keys))
+        }
+    }
+
+    #[test]
+    fn set_algebra() -> Result<(), String> {
+        let test_set = init();
+        let mut other = XfastSet::new(31);
+        for &key in [5, 7, 18].iter() {
+            other.insert(key);
+        }
+        let union: SetUnion<'_, usize> = test_set.union(&other);
+        let difference: SetDifference<'_, usize> = test_set.difference(&other);
+        let symmetric_difference: SetSymmetricDifference<'_, usize> = test_set.symmetric_difference(&other);
+        let keys = (union.collect::<Vec<_>>(), difference.collect::<Vec<_>>(), symmetric_difference.collect::<Vec<_>>());
+        if keys == (vec![1, 5, 7, 11, 18], vec![1, 11], vec![1, 7, 11]) && test_set.intersection(&other).eq([5, 18].iter().copied()) {
+            Ok(())
+        }
+        else {
+            Err(format!("Set algebra is wrong, got {:?}", keys))
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! A trie split into shards by the top bits of the keys, so that writers to different shards
+//! don't wait on each other.
+//!
+//! Every shard is an [`Xfast`] over the remaining low bits of its keys behind a lock of its own.
+//! A bitmap of the occupied shards lets queries skip the empty shards without locking them.
+
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{Xfast, XfastKey};
+
+// most top key bits selecting a shard, every shard costs a lock and a trie up front
+const MAX_SHARD_BITS: u32 = 16;
+
+/// A bitwise trie split into 2^k shards which can be written to in parallel.
+///
+/// Keys are sent to a shard by their top k bits, and every shard is locked on its own, so writers
+/// only wait on each other when they write to the same shard. Queries which run past the end of
+/// a shard continue in the next occupied one. Shards are locked one at a time, so a query spanning
+/// several shards may see some of the writes made to them while it runs and miss others. Values
+/// are handed out as clones since they can't outlive the lock of their shard.
+/// # Examples
+/// ```
+///     use std::sync::Arc;
+///     use std::thread;
+///     use xfast::ShardedXfast;
+///
+///     let test_trie: Arc<ShardedXfast<u32, u32>> = Arc::new(ShardedXfast::new(u32::MAX, 3));
+///     let writers: Vec<_> = (0..8u32).map(|writer| {
+///         let test_trie = Arc::clone(&test_trie);
+///         thread::spawn(move || {
+///             for key in 0..100 {
+///                 test_trie.insert((writer << 29) + key, writer);
+///             }
+///         })
+///     }).collect();
+///     for writer in writers {
+///         writer.join().unwrap();
+///     }
+///     assert_eq!(test_trie.len(), 800);
+///     assert_eq!(test_trie.successor(100), Some((1 << 29, 1)));
+/// ```
+#[derive(Debug)]
+pub struct ShardedXfast<K, V> {
+    nr_levels: usize,
+    // count of low key bits stored in the shards
+    shard_levels: usize,
+    shards: Vec<RwLock<Xfast<K, V>>>,
+    // bit set for every shard holding a key, kept up to date under the lock of the shard
+    occupied: Vec<AtomicU64>,
+    len: AtomicUsize,
+}
+
+impl<K: XfastKey, V: Clone> ShardedXfast<K, V> {
+
+    /// Creates a new sharded trie to store keys from zero up to `range` in 2^`shard_bits` shards
+    /// # Examples
+    /// ```
+    ///     use xfast::ShardedXfast;
+    ///
+    ///     let test_trie: ShardedXfast<u64, ()> = ShardedXfast::new(u64::MAX, 4);
+    ///     assert_eq!(test_trie.shard_count(), 16);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `shard_bits` is more than 16 or isn't smaller than the count of bits spanned by
+    /// `range`.
+    pub fn new(range: K, shard_bits: u32) -> Self {
+        assert!(shard_bits <= MAX_SHARD_BITS, "at most 2^16 shards are supported");
+        let nr_levels = Xfast::<K, V>::get_levels_count(range.to_bits());
+        let shard_bits = shard_bits as usize;
+        assert!(shard_bits < nr_levels, "shards need at least one key bit of their own");
+        let shard_levels = nr_levels - shard_bits;
+        let shard_count: usize = 1 << shard_bits;
+        ShardedXfast {
+            nr_levels,
+            shard_levels,
+            shards: (0..shard_count).map(|_| RwLock::new(Xfast::with_levels(shard_levels))).collect(),
+            occupied: (0..shard_count.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the count of shards the keys are split into
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the count of values stored in the trie
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    /// Returns true if the trie stores no values
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn max_key(&self) -> u128 {
+        u128::MAX >> (128 - self.nr_levels)
+    }
+
+    // shard of `key` and the key within the shard
+    fn split_key(&self, key: u128) -> (usize, K) {
+        let low_bits = key & (u128::MAX >> (128 - self.shard_levels));
+        ((key >> self.shard_levels) as usize, K::from_bits(low_bits))
+    }
+
+    // key made of a shard and a key within it
+    fn join_key(&self, shard: usize, key: K) -> K {
+        K::from_bits(((shard as u128) << self.shard_levels) | key.to_bits())
+    }
+
+    fn read_shard(&self, shard: usize) -> RwLockReadGuard<'_, Xfast<K, V>> {
+        self.shards[shard].read().expect("a writer panicked while holding a shard")
+    }
+
+    fn write_shard(&self, shard: usize) -> RwLockWriteGuard<'_, Xfast<K, V>> {
+        self.shards[shard].write().expect("a writer panicked while holding a shard")
+    }
+
+    fn set_occupied(&self, shard: usize, occupied: bool) {
+        let bit = 1u64 << (shard % 64);
+        if occupied {
+            self.occupied[shard / 64].fetch_or(bit, Ordering::SeqCst);
+        }
+        else {
+            self.occupied[shard / 64].fetch_and(!bit, Ordering::SeqCst);
+        }
+    }
+
+    // first occupied shard from `shard` on
+    fn next_occupied(&self, shard: usize) -> Option<usize> {
+        let mut word = shard / 64;
+        let mut bits = self.occupied.get(word)?.load(Ordering::SeqCst) & (u64::MAX << (shard % 64));
+        loop {
+            if bits != 0 {
+                return Some(word * 64 + bits.trailing_zeros() as usize);
+            }
+            word += 1;
+            bits = self.occupied.get(word)?.load(Ordering::SeqCst);
+        }
+    }
+
+    // last occupied shard up to `shard`
+    fn previous_occupied(&self, shard: usize) -> Option<usize> {
+        let mut word = shard / 64;
+        let mut bits = self.occupied[word].load(Ordering::SeqCst) & (u64::MAX >> (63 - shard % 64));
+        loop {
+            if bits != 0 {
+                return Some(word * 64 + 63 - bits.leading_zeros() as usize);
+            }
+            word = word.checked_sub(1)?;
+            bits = self.occupied[word].load(Ordering::SeqCst);
+        }
+    }
+
+    /// Returns a clone of the value stored with `key`
+    pub fn get(&self, key: K) -> Option<V> {
+        if key.to_bits() > self.max_key() {
+            return None;
+        }
+        let (shard, low_key) = self.split_key(key.to_bits());
+        self.read_shard(shard).get(low_key).cloned()
+    }
+
+    /// Returns true if the trie stores a value for `key`
+    pub fn contains_key(&self, key: K) -> bool {
+        if key.to_bits() > self.max_key() {
+            return false;
+        }
+        let (shard, low_key) = self.split_key(key.to_bits());
+        self.read_shard(shard).contains_key(low_key)
+    }
+
+    /// Insert `key` and `value` into the trie. If the key was already present its value is replaced
+    /// and the previous value is returned, otherwise it returns None. Only the shard of the key is
+    /// locked.
+    ///
+    /// # Panics
+    /// Panics if `key` is larger than the range the trie was created with.
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        assert!(key.to_bits() <= self.max_key(), "key out of the range of the trie");
+        let (shard, low_key) = self.split_key(key.to_bits());
+        let mut trie = self.write_shard(shard);
+        let previous = trie.insert(low_key, value);
+        if previous.is_none() {
+            self.len.fetch_add(1, Ordering::SeqCst);
+            if trie.len() == 1 {
+                self.set_occupied(shard, true);
+            }
+        }
+        previous
+    }
+
+    /// Delete a key from the trie. If the key doesn't exist it returns None else returns the value
+    /// that was stored with it. Only the shard of the key is locked.
+    pub fn remove(&self, key: K) -> Option<V> {
+        if key.to_bits() > self.max_key() {
+            return None;
+        }
+        let (shard, low_key) = self.split_key(key.to_bits());
+        let mut trie = self.write_shard(shard);
+        let removed = trie.remove(low_key)?;
+        self.len.fetch_sub(1, Ordering::SeqCst);
+        if trie.is_empty() {
+            self.set_occupied(shard, false);
+        }
+        Some(removed)
+    }
+
+    // smallest key >= `key` from the shard of `key` on
+    fn successor_bits(&self, key: u128) -> Option<(K, V)> {
+        if key > self.max_key() {
+            return None;
+        }
+        let (shard, low_key) = self.split_key(key);
+        if let Some((found, value)) = self.read_shard(shard).successor(low_key) {
+            return Some((self.join_key(shard, found), value.clone()));
+        }
+        // a shard may have been emptied since its bit was read
+        let mut next = shard + 1;
+        while let Some(occupied) = self.next_occupied(next) {
+            if let Some((found, value)) = self.read_shard(occupied).first_key_value() {
+                return Some((self.join_key(occupied, found), value.clone()));
+            }
+            next = occupied + 1;
+        }
+        None
+    }
+
+    // largest key <= `key` from the shard of `key` back
+    fn predecessor_bits(&self, key: u128) -> Option<(K, V)> {
+        let (shard, low_key) = self.split_key(key.min(self.max_key()));
+        if let Some((found, value)) = self.read_shard(shard).predecessor(low_key) {
+            return Some((self.join_key(shard, found), value.clone()));
+        }
+        let mut previous = shard.checked_sub(1)?;
+        while let Some(occupied) = self.previous_occupied(previous) {
+            if let Some((found, value)) = self.read_shard(occupied).last_key_value() {
+                return Some((self.join_key(occupied, found), value.clone()));
+            }
+            previous = occupied.checked_sub(1)?;
+        }
+        None
+    }
+
+    /// Returns the smallest key more than or equal to `key` along with a clone of its value,
+    /// looking into the next occupied shards when the shard of `key` holds no such key.
+    pub fn successor(&self, key: K) -> Option<(K, V)> {
+        self.successor_bits(key.to_bits())
+    }
+
+    /// Returns the largest key less than or equal to `key` along with a clone of its value,
+    /// looking into the previous occupied shards when the shard of `key` holds no such key.
+    pub fn predecessor(&self, key: K) -> Option<(K, V)> {
+        self.predecessor_bits(key.to_bits())
+    }
+
+    /// Returns the smallest key of the trie along with a clone of its value
+    pub fn first_key_value(&self) -> Option<(K, V)> {
+        self.successor_bits(0)
+    }
+
+    /// Returns the largest key of the trie along with a clone of its value
+    pub fn last_key_value(&self) -> Option<(K, V)> {
+        self.predecessor_bits(self.max_key())
+    }
+
+    /// Returns a double-ended iterator around the keys in `range` and clones of their values, in
+    /// ascending order of keys. Every step looks up the next key in a single shard, so keys
+    /// written while iterating may or may not be seen.
+    /// # Examples
+    /// ```
+    ///     use xfast::ShardedXfast;
+    ///
+    ///     let test_trie: ShardedXfast<u16, u16> = ShardedXfast::new(1023, 2);
+    ///     for key in (0..1024).step_by(100) {
+    ///         test_trie.insert(key, key);
+    ///     }
+    ///     let keys: Vec<u16> = test_trie.range(150..=700).map(|(key, _)| key).collect();
+    ///     assert_eq!(keys, vec![200, 300, 400, 500, 600, 700]);
+    /// ```
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> ShardedXfastRange<'_, K, V> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => Some(start.to_bits()),
+            Bound::Excluded(start) => start.to_bits().checked_add(1),
+            Bound::Unbounded => Some(0),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => Some(end.to_bits().min(self.max_key())),
+            Bound::Excluded(end) => end.to_bits().checked_sub(1).map(|end| end.min(self.max_key())),
+            Bound::Unbounded => Some(self.max_key()),
+        };
+        let bounds = match (start, end) {
+            (Some(start), Some(end)) if start <= end => Some((start, end)),
+            _ => None,
+        };
+        ShardedXfastRange { trie: self, bounds }
+    }
+
+    /// Returns an iterator around all the keys of the trie and clones of their values, in
+    /// ascending order of keys.
+    pub fn iter(&self) -> ShardedXfastRange<'_, K, V> {
+        self.range(..)
+    }
+}
+
+/// Double-ended iterator around the ShardedXfast keys in a range and clones of their values
+pub struct ShardedXfastRange<'a, K, V> {
+    trie: &'a ShardedXfast<K, V>,
+    // smallest and largest keys left to look at, None once the ends have met
+    bounds: Option<(u128, u128)>,
+}
+
+impl<'a, K: XfastKey, V: Clone> Iterator for ShardedXfastRange<'a, K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, end) = self.bounds?;
+        let (key, value) = match self.trie.successor_bits(start) {
+            Some((key, value)) if key.to_bits() <= end => (key, value),
+            _ => {
+                self.bounds = None;
+                return None;
+            }
+        };
+        self.bounds = if key.to_bits() == end { None } else { Some((key.to_bits() + 1, end)) };
+        Some((key, value))
+    }
+}
+
+impl<'a, K: XfastKey, V: Clone> DoubleEndedIterator for ShardedXfastRange<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (start, end) = self.bounds?;
+        let (key, value) = match self.trie.predecessor_bits(end) {
+            Some((key, value)) if key.to_bits() >= start => (key, value),
+            _ => {
+                self.bounds = None;
+                return None;
+            }
+        };
+        self.bounds = if key.to_bits() == start { None } else { Some((start, key.to_bits() - 1)) };
+        Some((key, value))
+    }
+}
+
+impl<'a, K: XfastKey, V: Clone> FusedIterator for ShardedXfastRange<'a, K, V> {}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::ShardedXfast;
+
+    #[test]
+    fn parallel_writers() -> Result<(), String> {
+        let test_trie: Arc<ShardedXfast<u32, u32>> = Arc::new(ShardedXfast::new(65535, 3));
+        let writers: Vec<_> = (0..8).map(|writer| {
+            let test_trie = Arc::clone(&test_trie);
+            thread::spawn(move || {
+                // every writer covers keys across all the shards
+                for key in (writer..65536).step_by(8 * 97) {
+                    test_trie.insert(key, writer);
+                }
+                for key in (writer..65536).step_by(8 * 97 * 2) {
+                    test_trie.remove(key);
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().map_err(|_| String::from("writer panicked"))?;
+        }
+        let mut expected: Vec<u32> = (0..8).flat_map(|writer| (writer..65536).step_by(8 * 97).skip(1).step_by(2)).collect();
+        expected.sort_unstable();
+        let keys: Vec<u32> = test_trie.iter().map(|(key, _)| key).collect();
+        if keys == expected && test_trie.len() == expected.len() && test_trie.iter().rev().map(|(key, _)| key).eq(expected.iter().rev().copied()) {
+            Ok(())
+        }
+        else {
+            Err(format!("Keys written in parallel are wrong, got {} keys", keys.len()))
+        }
+    }
+
+    #[test]
+    fn across_empty_shards() -> Result<(), String> {
+        let test_trie: ShardedXfast<u16, &str> = ShardedXfast::new(1023, 7);
+        test_trie.insert(5, "five");
+        test_trie.insert(900, "nine hundred");
+        test_trie.insert(1023, "last");
+        test_trie.remove(1023);
+        let found = (test_trie.successor(6), test_trie.predecessor(899), test_trie.successor(901), test_trie.predecessor(4));
+        let range: Vec<u16> = test_trie.range(5..900).map(|(key, _)| key).collect();
+        if found == (Some((900, "nine hundred")), Some((5, "five")), None, None) && range == vec![5]
+            && test_trie.last_key_value() == Some((900, "nine hundred")) && test_trie.range(901..).next().is_none() {
+            Ok(())
+        }
+        else {
+            Err(format!("Queries across empty shards are wrong, got {:?}", found))
+        }
+    }
+
+    #[test]
+    fn wide_shard_keys() -> Result<(), String> {
+        let test_trie: ShardedXfast<u64, u32> = ShardedXfast::new(u64::MAX, 16);
+        test_trie.insert(u64::MAX, 1);
+        test_trie.insert(1 << 47, 2);
+        let keys: Vec<u64> = test_trie.iter().map(|(key, _)| key).collect();
+        if test_trie.shard_count() == 1 << 16 && keys == vec![1 << 47, u64::MAX] && test_trie.predecessor(u64::MAX - 1) == Some((1 << 47, 2)) {
+            Ok(())
+        }
+        else {
+            Err(format!("Keys of the widest shard split are wrong, got {:?}", keys))
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 2^16 shards")]
+    fn too_many_shards() {
+        ShardedXfast::<u64, u32>::new(u64::MAX, 40);
+    }
+}
@@ -0,0 +1,440 @@
+//! Binary snapshots of a trie, written and read back without any dependencies.
+//!
+//! A snapshot holds three sections, each followed by the CRC-32 of its bytes. All integers are
+//! little endian.
+//!
+//! | section | contents                                                                    |
+//! |---------|-----------------------------------------------------------------------------|
+//! | header  | magic `XFST`, format version (u8), `nr_levels` (u8), key count (u64)        |
+//! | keys    | byte length (u64), then the first key and the gaps to every next key as varints |
+//! | values  | byte length (u64), then every value as a varint length and its encoded bytes |
+//!
+//! Varints hold 7 bits per byte, low bits first, with the high bit set on all but the last byte.
+//! Checksums are verified before a section is decoded, so a truncated or corrupted snapshot fails
+//! with a [`SnapshotError`] instead of producing a broken trie.
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::io::{self, Read, Write};
+
+use crate::{Xfast, XfastKey};
+
+const MAGIC: [u8; 4] = *b"XFST";
+const VERSION: u8 = 1;
+
+/// Encoding of the values stored in a snapshot
+pub trait ValueCodec<V> {
+    /// Appends the bytes of `value` to `out`
+    fn encode(&self, value: &V, out: &mut Vec<u8>);
+    /// Returns the value encoded as `bytes`, or None if they don't encode one
+    fn decode(&self, bytes: &[u8]) -> Option<V>;
+}
+
+/// Section of a snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotSection {
+    /// Magic bytes, format version, levels and key count
+    Header,
+    /// Sorted keys
+    Keys,
+    /// Values in the order of their keys
+    Values,
+}
+
+/// Error returned by [`Xfast::read_from`] for a snapshot a trie can't be read from
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Reading the snapshot failed
+    Io(io::Error),
+    /// The snapshot ended before all of its sections were read
+    Truncated,
+    /// The snapshot doesn't start with the magic bytes
+    BadMagic,
+    /// The snapshot was written in a format version this crate can't read
+    UnsupportedVersion(u8),
+    /// The bytes of a section don't match its checksum
+    ChecksumMismatch(SnapshotSection),
+    /// A section passed its checksum but doesn't describe a valid trie
+    Malformed(SnapshotSection, &'static str),
+    /// The value codec rejected the value stored at this position
+    InvalidValue(usize),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "reading the snapshot failed: {}", error),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::BadMagic => write!(f, "snapshot doesn't start with the magic bytes"),
+            SnapshotError::UnsupportedVersion(version) => write!(f, "snapshot format version {} isn't supported", version),
+            SnapshotError::ChecksumMismatch(section) => write!(f, "checksum of the {:?} section doesn't match", section),
+            SnapshotError::Malformed(section, reason) => write!(f, "{:?} section is malformed: {}", section, reason),
+            SnapshotError::InvalidValue(index) => write!(f, "value {} can't be decoded", index),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            SnapshotError::Truncated
+        }
+        else {
+            SnapshotError::Io(error)
+        }
+    }
+}
+
+// lookup table of the CRC-32 used by zlib and png
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+fn write_varint(mut value: u128, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// varint at the start of `bytes` along with the count of bytes it took
+fn read_varint(bytes: &[u8]) -> Option<(u128, usize)> {
+    let mut value = 0u128;
+    for (index, &byte) in bytes.iter().enumerate().take(19) {
+        let bits = u128::from(byte & 0x7f);
+        // the 19th byte only has room for the two highest bits of a u128
+        if index == 18 && bits > 0b11 {
+            return None;
+        }
+        value |= bits << (7 * index);
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+// write `bytes` as a section prefixed by its length and followed by its checksum
+fn write_section<W: Write>(writer: &mut W, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    writer.write_all(&crc32(bytes).to_le_bytes())
+}
+
+// read the bytes of a section written by `write_section`, once they match their checksum
+fn read_section<R: Read>(reader: &mut R, section: SnapshotSection) -> Result<Vec<u8>, SnapshotError> {
+    let mut length = [0u8; 8];
+    reader.read_exact(&mut length)?;
+    let length = u64::from_le_bytes(length);
+    // the length isn't trusted yet, so the buffer only grows with the bytes actually read
+    let mut bytes = Vec::new();
+    reader.by_ref().take(length).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < length {
+        return Err(SnapshotError::Truncated);
+    }
+    let mut checksum = [0u8; 4];
+    reader.read_exact(&mut checksum)?;
+    if u32::from_le_bytes(checksum) != crc32(&bytes) {
+        return Err(SnapshotError::ChecksumMismatch(section));
+    }
+    Ok(bytes)
+}
+
+impl<K: XfastKey, V, S: BuildHasher> Xfast<K, V, S> {
+
+    /// Writes a snapshot of the trie to `writer`, encoding the values with `codec`. The trie can
+    /// be read back with [`read_from`](Xfast::read_from).
+    /// # Examples
+    /// ```
+    ///     use xfast::{ValueCodec, Xfast};
+    ///
+    ///     struct Utf8;
+    ///
+    ///     impl ValueCodec<String> for Utf8 {
+    ///         fn encode(&self, value: &String, out: &mut Vec<u8>) {
+    ///             out.extend_from_slice(value.as_bytes());
+    ///         }
+    ///         fn decode(&self, bytes: &[u8]) -> Option<String> {
+    ///             String::from_utf8(bytes.to_vec()).ok()
+    ///         }
+    ///     }
+    ///
+    ///     let mut test_trie: Xfast<u32, String> = Xfast::new(1023);
+    ///     test_trie.insert(11, String::from("eleven"));
+    ///     test_trie.insert(1, String::from("one"));
+    ///
+    ///     let mut snapshot = Vec::new();
+    ///     test_trie.write_to(&mut snapshot, &Utf8).unwrap();
+    ///     let read: Xfast<u32, String> = Xfast::read_from(&snapshot[..], &Utf8).unwrap();
+    ///     assert_eq!(read.successor(2), Some((11, &String::from("eleven"))));
+    /// ```
+    pub fn write_to<W: Write, C: ValueCodec<V>>(&self, mut writer: W, codec: &C) -> io::Result<()> {
+        let mut header = Vec::with_capacity(14);
+        header.extend_from_slice(&MAGIC);
+        header.push(VERSION);
+        header.push(self.nr_levels as u8);
+        header.extend_from_slice(&(self.len() as u64).to_le_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(&crc32(&header).to_le_bytes())?;
+
+        let mut keys = Vec::new();
+        let mut previous = 0;
+        let mut value_bytes = Vec::new();
+        let mut values = Vec::new();
+        let mut leaf = self.min_leaf;
+        while let Some(node) = leaf {
+            let key = self.nodes[node].key;
+            // the first key is written as its gap from zero
+            write_varint(key - previous, &mut keys);
+            previous = key;
+
+            value_bytes.clear();
+            codec.encode(self.values.get(node), &mut value_bytes);
+            write_varint(value_bytes.len() as u128, &mut values);
+            values.extend_from_slice(&value_bytes);
+            leaf = self.nodes[node].right;
+        }
+        write_section(&mut writer, &keys)?;
+        write_section(&mut writer, &values)?;
+        writer.flush()
+    }
+}
+
+impl<K: XfastKey, V> Xfast<K, V> {
+
+    /// Reads a trie from a snapshot written by [`write_to`](Xfast::write_to), decoding the values
+    /// with `codec`. Returns an error if the snapshot is truncated, corrupted or doesn't fit the
+    /// key type.
+    /// # Examples
+    /// ```
+    ///     use xfast::{SnapshotError, ValueCodec, Xfast};
+    ///
+    ///     struct NoValues;
+    ///
+    ///     impl ValueCodec<()> for NoValues {
+    ///         fn encode(&self, _: &(), _: &mut Vec<u8>) {}
+    ///         fn decode(&self, bytes: &[u8]) -> Option<()> {
+    ///             if bytes.is_empty() { Some(()) } else { None }
+    ///         }
+    ///     }
+    ///
+    ///     let test_trie: Xfast<u32, ()> = Xfast::from_sorted_iter(1023, (0..100).map(|key| (key * 7, ())));
+    ///     let mut snapshot = Vec::new();
+    ///     test_trie.write_to(&mut snapshot, &NoValues).unwrap();
+    ///
+    ///     snapshot.truncate(snapshot.len() - 1);
+    ///     let read = Xfast::<u32, ()>::read_from(&snapshot[..], &NoValues);
+    ///     assert!(matches!(read, Err(SnapshotError::Truncated)));
+    /// ```
+    pub fn read_from<R: Read, C: ValueCodec<V>>(reader: R, codec: &C) -> Result<Self, SnapshotError> {
+        Self::read_from_with_hasher(reader, codec, RandomState::new())
+    }
+}
+
+impl<K: XfastKey, V, S: BuildHasher + Clone> Xfast<K, V, S> {
+
+    /// Reads a trie from a snapshot written by [`write_to`](Xfast::write_to), decoding the values
+    /// with `codec` and hashing the prefixes at every level with `hash_builder`. Works as
+    /// [`read_from`](Xfast::read_from) otherwise.
+    /// # Examples
+    /// ```
+    ///     use xfast::{BuildIntHasher, ValueCodec, Xfast};
+    ///
+    ///     struct NoValues;
+    ///
+    ///     impl ValueCodec<()> for NoValues {
+    ///         fn encode(&self, _: &(), _: &mut Vec<u8>) {}
+    ///         fn decode(&self, bytes: &[u8]) -> Option<()> {
+    ///             if bytes.is_empty() { Some(()) } else { None }
+    ///         }
+    ///     }
+    ///
+    ///     let pairs = (0..100).map(|key| (key * 7, ()));
+    ///     let test_trie: Xfast<u32, (), _> = Xfast::from_sorted_iter_with_hasher(1023, pairs, BuildIntHasher::with_seed(3));
+    ///     let mut snapshot = Vec::new();
+    ///     test_trie.write_to(&mut snapshot, &NoValues).unwrap();
+    ///
+    ///     let read: Xfast<u32, (), _> = Xfast::read_from_with_hasher(&snapshot[..], &NoValues, BuildIntHasher::with_seed(3)).unwrap();
+    ///     assert_eq!(read.successor(8), Some((14, &())));
+    /// ```
+    pub fn read_from_with_hasher<R: Read, C: ValueCodec<V>>(mut reader: R, codec: &C, hash_builder: S) -> Result<Self, SnapshotError> {
+        let mut header = [0u8; 14];
+        reader.read_exact(&mut header)?;
+        let mut checksum = [0u8; 4];
+        reader.read_exact(&mut checksum)?;
+        if header[..4] != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        if u32::from_le_bytes(checksum) != crc32(&header) {
+            return Err(SnapshotError::ChecksumMismatch(SnapshotSection::Header));
+        }
+        if header[4] != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header[4]));
+        }
+        let nr_levels = header[5] as usize;
+        if nr_levels == 0 || nr_levels > K::BITS {
+            return Err(SnapshotError::Malformed(SnapshotSection::Header, "levels don't fit the key type"));
+        }
+        let mut count = [0u8; 8];
+        count.copy_from_slice(&header[6..]);
+        let count = u64::from_le_bytes(count);
+
+        let keys = read_section(&mut reader, SnapshotSection::Keys)?;
+        let values = read_section(&mut reader, SnapshotSection::Values)?;
+
+        let trie = Self::with_levels_and_hasher(nr_levels, 0, hash_builder);
+        let malformed_keys = |reason| SnapshotError::Malformed(SnapshotSection::Keys, reason);
+        let mut pairs = Vec::new();
+        let (mut key_offset, mut value_offset) = (0, 0);
+        let mut previous: Option<u128> = None;
+        while key_offset < keys.len() || value_offset < values.len() {
+            if pairs.len() as u64 == count {
+                let section = if key_offset < keys.len() { SnapshotSection::Keys } else { SnapshotSection::Values };
+                return Err(SnapshotError::Malformed(section, "more entries than the key count"));
+            }
+            let (gap, taken) = read_varint(&keys[key_offset..]).ok_or_else(|| malformed_keys("bad varint"))?;
+            key_offset += taken;
+            let key = match previous {
+                None => gap,
+                Some(_) if gap == 0 => return Err(malformed_keys("keys aren't strictly increasing")),
+                Some(previous) => previous.checked_add(gap).ok_or_else(|| malformed_keys("key overflows"))?,
+            };
+            if key > trie.max_key() {
+                return Err(malformed_keys("key is out of the range of the trie"));
+            }
+            previous = Some(key);
+
+            let malformed_values = |reason| SnapshotError::Malformed(SnapshotSection::Values, reason);
+            let (length, taken) = read_varint(&values[value_offset..]).ok_or_else(|| malformed_values("bad varint"))?;
+            value_offset += taken;
+            let end = value_offset.checked_add(length as usize)
+                .filter(|&end| end <= values.len() && length <= usize::MAX as u128)
+                .ok_or_else(|| malformed_values("value runs past the end of the section"))?;
+            let value = codec.decode(&values[value_offset..end]).ok_or(SnapshotError::InvalidValue(pairs.len()))?;
+            value_offset = end;
+            pairs.push((K::from_bits(key), value));
+        }
+        if pairs.len() as u64 != count {
+            return Err(malformed_keys("fewer entries than the key count"));
+        }
+        trie.build_sorted(pairs, true).map_err(|_| malformed_keys("keys don't describe a trie"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SnapshotError, SnapshotSection, ValueCodec};
+    use crate::{BuildIntHasher, Xfast};
+
+    struct LeBytes;
+
+    impl ValueCodec<u64> for LeBytes {
+        fn encode(&self, value: &u64, out: &mut Vec<u8>) {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+
+        fn decode(&self, bytes: &[u8]) -> Option<u64> {
+            let mut value = [0u8; 8];
+            if bytes.len() != 8 {
+                return None;
+            }
+            value.copy_from_slice(bytes);
+            Some(u64::from_le_bytes(value))
+        }
+    }
+
+    fn init() -> Vec<u8> {
+        let mut test_trie: Xfast<u64, u64> = Xfast::default();
+        for key in [0, 1, 127, 128, 1 << 40, u64::MAX].iter() {
+            test_trie.insert(*key, key.wrapping_mul(3));
+        }
+        let mut snapshot = Vec::new();
+        test_trie.write_to(&mut snapshot, &LeBytes).expect("writing to a vec");
+        snapshot
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), String> {
+        let snapshot = init();
+        let read = Xfast::<u64, u64>::read_from(&snapshot[..], &LeBytes).map_err(|error| error.to_string())?;
+        let hashed: Xfast<u64, u64, _> = Xfast::read_from_with_hasher(&snapshot[..], &LeBytes, BuildIntHasher::with_seed(2)).map_err(|error| error.to_string())?;
+        let pairs: Vec<(u64, u64)> = read.iter().map(|(key, &value)| (key, value)).collect();
+        let expected: Vec<(u64, u64)> = [0, 1, 127, 128, 1 << 40, u64::MAX].iter().map(|&key: &u64| (key, key.wrapping_mul(3))).collect();
+        if pairs == expected && read.successor(129) == Some((1 << 40, &(3 << 40))) && hashed.iter().eq(read.iter()) && hashed.successor(129) == read.successor(129) {
+            Ok(())
+        }
+        else {
+            Err(format!("Snapshot read back is wrong, got {:?}", pairs))
+        }
+    }
+
+    #[test]
+    fn damaged_snapshots() -> Result<(), String> {
+        let snapshot = init();
+        let read = |bytes: &[u8]| Xfast::<u64, u64>::read_from(bytes, &LeBytes).err();
+        let flipped = |index: usize| {
+            let mut bytes = snapshot.clone();
+            bytes[index] ^= 0x10;
+            read(&bytes)
+        };
+        let keys_start = 18;
+        let values_start = keys_start + 8 + snapshot[keys_start] as usize + 4;
+        let results = vec![
+            read(&snapshot[..snapshot.len() - 2]),
+            read(&snapshot[..10]),
+            flipped(0),
+            flipped(8),
+            flipped(keys_start + 9),
+            flipped(values_start + 9),
+        ];
+        let ok = matches!(&results[..], [Some(SnapshotError::Truncated), Some(SnapshotError::Truncated), Some(SnapshotError::BadMagic),
+            Some(SnapshotError::ChecksumMismatch(SnapshotSection::Header)),
+            Some(SnapshotError::ChecksumMismatch(SnapshotSection::Keys)),
+            Some(SnapshotError::ChecksumMismatch(SnapshotSection::Values))]);
+        if ok {
+            Ok(())
+        }
+        else {
+            Err(format!("Damaged snapshots should be rejected, got {:?}", results))
+        }
+    }
+
+    #[test]
+    fn narrower_key_type() -> Result<(), String> {
+        let snapshot = init();
+        match Xfast::<u32, u64>::read_from(&snapshot[..], &LeBytes) {
+            Err(SnapshotError::Malformed(SnapshotSection::Header, _)) => Ok(()),
+            other => Err(format!("Snapshot of wider keys should be rejected, got {:?}", other.map(|trie| trie.len()))),
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! Splitting a trie at a key and appending one trie to another.
+//!
+//! The keys on either side of a boundary only share the internal nodes on the path from the root
+//! to their lowest common ancestor. Splitting moves the nodes of the smaller side over to the new
+//! trie, which gets its own copy of the shared path, while the other side keeps its nodes and only
+//! relinks the boundary leaf and the descendant ptrs along the shared path, which now refer to
+//! that leaf. Appending tries with disjoint key ranges moves the nodes of the smaller trie over and
+//! merges the shared path, taking the descendant ptrs toward the appended keys from the nodes
+//! merged in.
+
+use std::hash::BuildHasher;
+use std::mem;
+
+use crate::{Node, TrieNode, Xfast, XfastKey};
+
+impl<K: XfastKey, V, S: BuildHasher + Clone> Xfast<K, V, S> {
+
+    // empty trie with the same universe, keeping order statistics if the trie does
+    fn empty_like(&self) -> Self {
+        let mut empty = self.empty_with_same_levels();
+        if self.subtree_sizes.is_some() {
+            empty.subtree_sizes = Some(Vec::new());
+        }
+        empty
+    }
+
+    // internal nodes on the path of `key`, from the parent of its leaf up to the root
+    fn path_nodes(&self, key: u128) -> Vec<Node> {
+        (0..self.nr_levels).rev()
+            .filter_map(|level| self.levels.get(level, self.prefix(key, level)))
+            .collect()
+    }
+
+    // subtree sizes of the nodes shared by both sides of a boundary, given the key of a leaf next
+    // to the boundary
+    fn recount_boundary_path(&mut self, key: u128) {
+        if self.subtree_sizes.is_some() {
+            let path = self.path_nodes(key);
+            self.recount_subtree_sizes(path);
+        }
+    }
+
+    /// Splits the trie in two at `key`. Returns a trie with the same range holding every key
+    /// larger than or equal to `key`, while the smaller keys are left in `self`.
+    ///
+    /// The nodes of the smaller of the two parts are moved over as they are, so splitting takes
+    /// time linear in the size of that part and O(log U) for the nodes along the boundary.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<u32, u32> = Xfast::from_sorted_iter(1023, (0..100).map(|key| (key, key)));
+    ///     let upper = test_trie.split_off(60);
+    ///     assert_eq!(test_trie.len(), 60);
+    ///     assert_eq!(test_trie.last_key_value(), Some((59, &59)));
+    ///     assert_eq!(upper.len(), 40);
+    ///     assert_eq!(upper.first_key_value(), Some((60, &60)));
+    /// ```
+    pub fn split_off(&mut self, key: K) -> Self {
+        let mut split = self.empty_like();
+        let first_upper = match self.successor_node(key.to_bits()) {
+            Some(node) => node,
+            None => return split,
+        };
+        let last_lower = match self.nodes[first_upper].left {
+            Some(node) => node,
+            None => {
+                mem::swap(self, &mut split);
+                return split;
+            }
+        };
+
+        // walk away from the boundary on both sides to find the smaller one
+        let (mut lower, mut upper) = (Some(last_lower), Some(first_upper));
+        let move_upper = loop {
+            match (lower, upper) {
+                (_, None) => break true,
+                (None, _) => break false,
+                (Some(lower_node), Some(upper_node)) => {
+                    lower = self.nodes[lower_node].left;
+                    upper = self.nodes[upper_node].right;
+                }
+            }
+        };
+        let (moved_end, kept_end) = if move_upper { (first_upper, last_lower) } else { (last_lower, first_upper) };
+        let boundary = self.nodes[kept_end].key;
+
+        // the leaves on both sides share the ancestors of their lowest common ancestor
+        let lower_key = self.nodes[last_lower].key;
+        let upper_key = self.nodes[first_upper].key;
+        let shared_levels = (0..self.nr_levels)
+            .take_while(|&level| self.prefix(lower_key, level) == self.prefix(upper_key, level))
+            .count();
+
+        // move the leaves of the moved side over to the split trie along with the nodes only they
+        // descend from, keeping the nodes and their ptrs as they are
+        let mut remap: Vec<Node> = vec![0; self.nodes.capacity()];
+        let mut moved: Vec<Node> = Vec::new();
+        let mut far_end = moved_end;
+        let mut leaf = Some(moved_end);
+        while let Some(node) = leaf {
+            leaf = if move_upper { self.nodes[node].right } else { self.nodes[node].left };
+            far_end = node;
+            let key = self.nodes[node].key;
+            let mut detached = vec![(self.nr_levels, key, node)];
+            self.levels.remove(self.nr_levels, key);
+            for level in (1..self.nr_levels).rev() {
+                let prefix = self.prefix(key, level);
+                if level < shared_levels && prefix == self.prefix(boundary, level) {
+                    break;
+                }
+                match self.levels.remove(level, prefix) {
+                    Some(ancestor) => detached.push((level, prefix, ancestor)),
+                    // the ancestors above were moved along with an earlier leaf
+                    None => break,
+                }
+            }
+            for (level, prefix, old_node) in detached {
+                // freed slots are left at zero for the nodes reusing them
+                if let Some(sizes) = self.subtree_sizes.as_mut() {
+                    sizes[old_node] = 0;
+                }
+                let new_node = split.nodes.alloc(self.nodes.free(old_node));
+                if level == self.nr_levels {
+                    split.values.insert(new_node, self.values.remove(old_node));
+                }
+                split.levels.insert(level, prefix, new_node);
+                remap[old_node] = new_node;
+                moved.push(new_node);
+            }
+        }
+        // moved nodes only refer to nodes of the moved side, but for the leaf at the boundary
+        for &node in moved.iter() {
+            let moved_node = &mut split.nodes[node];
+            moved_node.left = moved_node.left.map(|target| remap[target]);
+            moved_node.right = moved_node.right.map(|target| remap[target]);
+        }
+        let (new_moved_end, new_far_end) = (remap[moved_end], remap[far_end]);
+        if move_upper {
+            split.nodes[new_moved_end].left = None;
+            split.min_leaf = Some(new_moved_end);
+            split.max_leaf = Some(new_far_end);
+        }
+        else {
+            split.nodes[new_moved_end].right = None;
+            split.min_leaf = Some(new_far_end);
+            split.max_leaf = Some(new_moved_end);
+        }
+
+        // the split trie gets its own copy of the shared nodes, built bottom-up so that the copies
+        // below are linked in first. The shared nodes drop the children which were moved, and the
+        // leaves at the boundary become the largest (or smallest) leaves below every one of them.
+        for level in (0..shared_levels).rev() {
+            let prefix = self.prefix(boundary, level);
+            let node = self.levels.node(level, prefix);
+            let copy = match split.levels.get(level, prefix) {
+                Some(root) => root,
+                None => {
+                    let copy = split.nodes.alloc(TrieNode::new_internal(level));
+                    split.levels.insert(level, prefix, copy);
+                    copy
+                }
+            };
+            for bit in 0..2 {
+                if let Some(child) = split.levels.get(level + 1, prefix << 1 | bit) {
+                    split.link_child(copy, bit, child);
+                }
+                let child = if bit == 0 { self.nodes[node].left_child() } else { self.nodes[node].right_child() };
+                if child.is_some() && !self.levels.contains(level + 1, prefix << 1 | bit) {
+                    self.unlink_child(node, bit);
+                }
+            }
+            // a side the copy is missing was missing in the shared node as well, unless it
+            // faces the boundary, and its descendant ptr referred to a moved leaf
+            let (shared_left, shared_right) = (self.nodes[node].left, self.nodes[node].right);
+            let copy_node = &mut split.nodes[copy];
+            if copy_node.is_desc_left {
+                copy_node.left = if move_upper { Some(new_moved_end) } else { shared_left.map(|leaf| remap[leaf]) };
+            }
+            if copy_node.is_desc_right {
+                copy_node.right = if move_upper { shared_right.map(|leaf| remap[leaf]) } else { Some(new_moved_end) };
+            }
+            let shared_node = &mut self.nodes[node];
+            if move_upper && shared_node.is_desc_right {
+                shared_node.right = Some(kept_end);
+            }
+            if !move_upper && shared_node.is_desc_left {
+                shared_node.left = Some(kept_end);
+            }
+        }
+
+        // cut the linked list of leaves at the boundary
+        if move_upper {
+            self.nodes[kept_end].right = None;
+            self.max_leaf = Some(kept_end);
+        }
+        else {
+            self.nodes[kept_end].left = None;
+            self.min_leaf = Some(kept_end);
+        }
+        self.recount_boundary_path(boundary);
+        // the entries come top-down level by level, so the children are counted first in reverse
+        let split_nodes: Vec<Node> = split.levels.entries().into_iter().rev().map(|(_, _, node)| node).collect();
+        split.recount_subtree_sizes(split_nodes);
+
+        if !move_upper {
+            mem::swap(self, &mut split);
+        }
+        split
+    }
+
+    /// Moves every key of `other` into `self`, leaving `other` empty. Values of `other` replace
+    /// the values stored with the same keys in `self`.
+    ///
+    /// When all the keys of one trie are smaller than all the keys of the other, the nodes of the
+    /// smaller trie are moved over as they are and only the nodes along the boundary are merged,
+    /// in time linear in the size of the smaller trie. Otherwise the keys of `other` are inserted
+    /// one at a time.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<u32, u32> = Xfast::from_sorted_iter(1023, (0..60).map(|key| (key, key)));
+    ///     let mut other = Xfast::from_sorted_iter(1023, (60..100).map(|key| (key, key)));
+    ///     test_trie.append(&mut other);
+    ///     assert_eq!(test_trie.len(), 100);
+    ///     assert_eq!(test_trie.successor(59), Some((59, &59)));
+    ///     assert_eq!(test_trie.successor(60), Some((60, &60)));
+    ///     assert!(other.is_empty());
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the tries don't have the same number of levels.
+    pub fn append(&mut self, other: &mut Self) {
+        assert_eq!(self.nr_levels, other.nr_levels, "appended tries need the same number of levels");
+        let empty = other.empty_like();
+        let mut other = mem::replace(other, empty);
+        if other.is_empty() {
+            return;
+        }
+        let order_statistics = self.subtree_sizes.is_some();
+        let (flat, direct_levels) = (self.levels.is_flat(), self.levels.direct_levels());
+
+        let (self_min, self_max) = match (self.min_leaf, self.max_leaf) {
+            (Some(min), Some(max)) => (self.nodes[min].key, self.nodes[max].key),
+            _ => (u128::MAX, 0),
+        };
+        let (other_min, other_max) = match (other.min_leaf, other.max_leaf) {
+            (Some(min), Some(max)) => (other.nodes[min].key, other.nodes[max].key),
+            _ => unreachable!("non-empty trie without extreme leaves"),
+        };
+        if self.is_empty() {
+            mem::swap(self, &mut other);
+        }
+        else if self_max < other_min || other_max < self_min {
+            if self.len() < other.len() {
+                mem::swap(self, &mut other);
+            }
+            self.graft(other);
+        }
+        else {
+            // the key ranges overlap, so the leaves of `other` are interleaved with ours
+            let mut leaf = other.min_leaf;
+            while let Some(node) = leaf {
+                leaf = other.nodes[node].right;
+                let key = K::from_bits(other.nodes[node].key);
+                let value = other.values.remove(node);
+                self.insert(key, value);
+            }
+        }
+
+        if order_statistics != self.subtree_sizes.is_some() {
+            if order_statistics {
+                let trie = mem::replace(self, self.empty_with_same_levels());
+                *self = trie.with_order_statistics();
+            }
+            else {
+                self.subtree_sizes = None;
+            }
+        }
+        // the trie swapped in keeps the nodes of its levels the way `other` did
+        self.levels.set_layout(flat, direct_levels);
+    }
+
+    // move the nodes of the non-empty `other`, whose keys are all smaller or all larger than ours,
+    // into the trie and merge the nodes present in both
+    fn graft(&mut self, mut other: Self) {
+        let other_above = other.nodes[other.min_leaf.expect("trie to graft is empty")].key
+            > self.nodes[self.max_leaf.expect("trie grafted onto is empty")].key;
+
+        // nodes of `other` mapped to the nodes replacing them in the trie
+        let mut remap: Vec<Node> = vec![0; other.nodes.capacity()];
+        let mut moved: Vec<Node> = Vec::new();
+        let mut shared: Vec<(Node, Node)> = Vec::new();
+        for (level, prefix, node) in other.levels.entries() {
+            match self.levels.get(level, prefix) {
+                Some(existing) => {
+                    remap[node] = existing;
+                    shared.push((node, existing));
+                }
+                None => {
+                    let new_node = self.nodes.alloc(other.nodes.free(node));
+                    if level == other.nr_levels {
+                        self.values.insert(new_node, other.values.remove(node));
+                    }
+                    self.levels.insert(level, prefix, new_node);
+                    remap[node] = new_node;
+                    moved.push(new_node);
+                }
+            }
+        }
+
+        // moved nodes only refer to nodes of their own subtree, none of which is shared
+        for &node in moved.iter() {
+            let moved_node = &mut self.nodes[node];
+            moved_node.left = moved_node.left.map(|target| remap[target]);
+            moved_node.right = moved_node.right.map(|target| remap[target]);
+        }
+        for &(theirs, ours) in shared.iter() {
+            if let Some(child) = other.nodes[theirs].left_child() {
+                self.link_child(ours, 0, remap[child]);
+            }
+            if let Some(child) = other.nodes[theirs].right_child() {
+                self.link_child(ours, 1, remap[child]);
+            }
+            // a side still missing a child is missing in both, and its descendant ptr refers to
+            // the largest (or smallest) leaf of whichever trie has the larger (or smaller) keys
+            let their_node = &other.nodes[theirs];
+            let (their_left, their_right) = (their_node.left, their_node.right);
+            let shared_node = &mut self.nodes[ours];
+            if other_above && shared_node.is_desc_right {
+                shared_node.right = their_right.map(|leaf| remap[leaf]);
+            }
+            if !other_above && shared_node.is_desc_left {
+                shared_node.left = their_left.map(|leaf| remap[leaf]);
+            }
+        }
+
+        // join the linked lists of leaves at the boundary
+        let other_min = remap[other.min_leaf.expect("trie to graft is empty")];
+        let other_max = remap[other.max_leaf.expect("trie to graft is empty")];
+        let (last_lower, first_upper) = if other_above {
+            let last_lower = self.max_leaf.expect("trie grafted onto is empty");
+            self.max_leaf = Some(other_max);
+            (last_lower, other_min)
+        }
+        else {
+            let first_upper = self.min_leaf.expect("trie grafted onto is empty");
+            self.min_leaf = Some(other_min);
+            (other_max, first_upper)
+        };
+        self.nodes[last_lower].right = Some(first_upper);
+        self.nodes[first_upper].left = Some(last_lower);
+
+        // moved nodes come top-down level by level, so their children are counted first in reverse
+        self.recount_subtree_sizes(moved.into_iter().rev());
+        let boundary = self.nodes[last_lower].key;
+        self.recount_boundary_path(boundary);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Xfast;
+
+    fn init(keys: &[u16]) -> Xfast<u16, u16> {
+        Xfast::from_sorted_iter(1023, keys.iter().map(|&key| (key, key)))
+    }
+
+    // keys found by walking the successors and predecessors of every key of the universe
+    fn neighbours(test_trie: &Xfast<u16, u16>) -> Vec<(Option<u16>, Option<u16>)> {
+        (0..1024).map(|key| (test_trie.successor(key).map(|(found, _)| found), test_trie.predecessor(key).map(|(found, _)| found)))
+            .collect()
+    }
+
+    // count of nodes at every level of the trie
+    fn level_sizes(test_trie: &Xfast<u16, u16>) -> Vec<usize> {
+        (0..=test_trie.nr_levels).map(|level| test_trie.levels.len(level)).collect()
+    }
+
+    // keys of the leaves the descendant ptrs of every internal node refer to
+    fn descendant_keys(test_trie: &Xfast<u16, u16>) -> Vec<(usize, u128, Option<u128>, Option<u128>)> {
+        let mut entries: Vec<_> = test_trie.levels.entries().into_iter().filter(|&(level, _, _)| level < test_trie.nr_levels).map(|(level, prefix, node)| {
+            let trie_node = &test_trie.nodes[node];
+            let leaf_key = |leaf: Option<usize>| leaf.map(|leaf| test_trie.nodes[leaf].key);
+            let left = if trie_node.is_desc_left { leaf_key(trie_node.left) } else { None };
+            let right = if trie_node.is_desc_right { leaf_key(trie_node.right) } else { None };
+            (level, prefix, left, right)
+        }).collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    fn same_trie(test_trie: &Xfast<u16, u16>, keys: &[u16]) -> bool {
+        let expected = init(keys);
+        neighbours(test_trie) == neighbours(&expected) && level_sizes(test_trie) == level_sizes(&expected)
+            && descendant_keys(test_trie) == descendant_keys(&expected)
+            && test_trie.nodes.len() == level_sizes(test_trie).iter().sum::<usize>()
+            && test_trie.iter().eq(expected.iter()) && test_trie.iter().rev().eq(expected.iter().rev())
+    }
+
+    #[test]
+    fn split_either_side() -> Result<(), String> {
+        let keys: Vec<u16> = (0..1024).filter(|key| key % 7 == 3).collect();
+        // keys packed into a subtree leave the top of the shared path without a child on either side
+        let packed: Vec<u16> = (300..400).filter(|key| key % 7 == 3).collect();
+        let splits = [0, 3, 4, 100, 511, 512, 900, 1020, 1023].iter().map(|&at| (&keys, at))
+            .chain([300, 320, 350, 390].iter().map(|&at| (&packed, at)));
+        for (keys, at) in splits {
+            let mut lower = init(keys);
+            let upper = lower.split_off(at);
+            let (lower_keys, upper_keys): (Vec<u16>, Vec<u16>) = keys.iter().partition(|&&key| key < at);
+            if !same_trie(&lower, &lower_keys) || !same_trie(&upper, &upper_keys) {
+                return Err(format!("Splitting at {} is wrong", at));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn split_keeps_statistics() -> Result<(), String> {
+        let keys: Vec<u16> = (0..1024).filter(|key| key % 5 == 0).collect();
+        let mut lower = init(&keys).with_order_statistics();
+        let upper = lower.split_off(100);
+        // the new nodes reuse the slots freed by the split
+        lower.insert(103, 103);
+        if lower.rank(50) == 10 && lower.select(20) == Some((103, &103)) && lower.rank(1023) == 21 && upper.rank(200) == 20 && upper.select(0) == Some((100, &100)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Order statistics are wrong after splitting"))
+        }
+    }
+
+    #[test]
+    fn append_disjoint() -> Result<(), String> {
+        let keys: Vec<u16> = (0..1024).filter(|key| key % 7 == 3).collect();
+        for &at in [1, 100, 511, 512, 1000].iter() {
+            let (lower_keys, upper_keys): (Vec<u16>, Vec<u16>) = keys.iter().partition(|&&key| key < at);
+            let mut test_trie = init(&lower_keys).with_order_statistics();
+            test_trie.append(&mut init(&upper_keys));
+            let mut reversed = init(&upper_keys);
+            reversed.append(&mut init(&lower_keys));
+            if !same_trie(&test_trie, &keys) || !same_trie(&reversed, &keys) || test_trie.rank(at) != lower_keys.len() {
+                return Err(format!("Appending at {} is wrong", at));
+            }
+        }
+        // the paths from the root are missing a child on the side of the appended keys
+        let mut below = init(&[1021, 1022]);
+        below.append(&mut init(&[1018]));
+        let mut above = init(&[1, 2]);
+        above.append(&mut init(&[5]));
+        if !same_trie(&below, &[1018, 1021, 1022]) || !same_trie(&above, &[1, 2, 5]) {
+            return Err(String::from("Appending next to the end of a trie is wrong"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn append_overlapping() -> Result<(), String> {
+        let mut test_trie = init(&[1, 5, 9]);
+        let mut other: Xfast<u16, u16> = Xfast::from_sorted_iter(1023, vec![(5, 50), (7, 70)]);
+        test_trie.append(&mut other);
+        let pairs: Vec<(u16, &u16)> = test_trie.iter().collect();
+        if pairs == vec![(1, &1), (5, &50), (7, &70), (9, &9)] && other.is_empty() && other.successor(0).is_none() {
+            Ok(())
+        }
+        else {
+            Err(format!("Overlapping tries are merged wrong, got {:?}", pairs))
+        }
+    }
+
+    #[test]
+    fn moved_values_dropped_once() -> Result<(), String> {
+        use std::rc::Rc;
+
+        let value = Rc::new(());
+        let mut test_trie: Xfast<u16, Rc<()>> = Xfast::from_sorted_iter(1023, (0..100).map(|key| (key * 3, Rc::clone(&value))));
+        let mut upper = test_trie.split_off(40);
+        let mut lower = test_trie.split_off(10);
+        upper.append(&mut lower);
+        let mut other: Xfast<u16, Rc<()>> = Xfast::from_sorted_iter(1023, (0..10).map(|key| (key * 7, Rc::clone(&value))));
+        upper.append(&mut other);
+        let count = Rc::strong_count(&value);
+        drop((test_trie, upper, lower, other));
+        if count == 108 && Rc::strong_count(&value) == 1 {
+            Ok(())
+        }
+        else {
+            Err(format!("Every value should be dropped once, {} were alive", count))
+        }
+    }
+}
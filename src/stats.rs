@@ -0,0 +1,307 @@
+//! Order statistics over the keys of the trie.
+//!
+//! When enabled, every internal node keeps the count of leaves in its subtree, so ranks and
+//! selections are answered by a single walk from the root in O(log U). Without the counts the
+//! same queries fall back to walking the linked list of leaves.
+
+use std::hash::BuildHasher;
+use std::ops::RangeBounds;
+
+use crate::{Node, Xfast, XfastKey, XfastRange};
+
+impl<K: XfastKey, V, S: BuildHasher> Xfast<K, V, S> {
+
+    /// Keeps the count of leaves below every internal node, which makes [`rank`](Xfast::rank),
+    /// [`select`](Xfast::select), [`count_range`](Xfast::count_range) and
+    /// [`sample`](Xfast::sample) run in O(log U) instead of linear time.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, &str> = Xfast::new(31).with_order_statistics();
+    ///     test_trie.insert(11, "eleven");
+    ///     test_trie.insert(1, "one");
+    ///     test_trie.insert(5, "five");
+    ///     assert_eq!(test_trie.rank(6), 2);
+    ///     assert_eq!(test_trie.select(2), Some((11, &"eleven")));
+    /// ```
+    pub fn with_order_statistics(mut self) -> Self {
+        if self.subtree_sizes.is_none() {
+            self.subtree_sizes = Some(Vec::new());
+            // count the leaves already present in the trie
+            let mut leaf = self.min_leaf;
+            while let Some(node) = leaf {
+                let key = self.nodes[node].key;
+                self.update_subtree_sizes(key, true);
+                leaf = self.nodes[node].right;
+            }
+        }
+        self
+    }
+
+    // account for the leaf `key` being added to (or removed from) the subtrees along its path
+    pub(crate) fn update_subtree_sizes(&mut self, key: u128, added: bool) {
+        let mut sizes = match self.subtree_sizes.take() {
+            Some(sizes) => sizes,
+            None => return,
+        };
+        // slots of the leaves and of the freed nodes are left at zero
+        if sizes.len() < self.nodes.capacity() {
+            sizes.resize(self.nodes.capacity(), 0);
+        }
+        for level in 0..self.nr_levels {
+            let node = self.levels.node(level, self.prefix(key, level));
+            if added {
+                sizes[node] += 1;
+            }
+            else {
+                sizes[node] -= 1;
+            }
+        }
+        self.subtree_sizes = Some(sizes);
+    }
+
+    // recount the leaves below `nodes` out of the counts of their children, children coming first
+    pub(crate) fn recount_subtree_sizes<I: IntoIterator<Item = Node>>(&mut self, nodes: I) {
+        let mut sizes = match self.subtree_sizes.take() {
+            Some(sizes) => sizes,
+            None => return,
+        };
+        if sizes.len() < self.nodes.capacity() {
+            sizes.resize(self.nodes.capacity(), 0);
+        }
+        for node in nodes {
+            let internal = &self.nodes[node];
+            if internal.level != self.nr_levels {
+                let children = [internal.left_child(), internal.right_child()];
+                sizes[node] = children.iter().flatten().map(|&child| self.subtree_size(&sizes, child)).sum();
+            }
+        }
+        self.subtree_sizes = Some(sizes);
+    }
+
+    // count of leaves in the subtree of `node`
+    fn subtree_size(&self, sizes: &[usize], node: Node) -> usize {
+        if self.nodes[node].level == self.nr_levels { 1 } else { sizes[node] }
+    }
+
+    // count of keys smaller than `key`
+    fn rank_bits(&self, key: u128) -> usize {
+        if key > self.max_key() {
+            return self.len();
+        }
+        let sizes = match &self.subtree_sizes {
+            Some(sizes) => sizes,
+            None => return self.iter().take_while(|&(leaf_key, _)| leaf_key.to_bits() < key).count(),
+        };
+
+        // follow the path of `key` and count the leaves hanging to its left
+        let mut rank = 0;
+        let mut node = self.levels.node(0, 0);
+        for level in 0..self.nr_levels {
+            let internal_node = &self.nodes[node];
+            let next_node = if (key >> (self.nr_levels - level - 1)) & 1 != 0 {
+                if let Some(left_node) = internal_node.left_child() {
+                    rank += self.subtree_size(sizes, left_node);
+                }
+                internal_node.right_child()
+            }
+            else {
+                internal_node.left_child()
+            };
+            match next_node {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        rank
+    }
+
+    /// Returns the count of keys in the trie smaller than `key`.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, ()> = Xfast::new(31).with_order_statistics();
+    ///     for &key in [3, 9, 27].iter() {
+    ///         test_trie.insert(key, ());
+    ///     }
+    ///     assert_eq!(test_trie.rank(3), 0);
+    ///     assert_eq!(test_trie.rank(10), 2);
+    ///     assert_eq!(test_trie.rank(31), 3);
+    /// ```
+    pub fn rank(&self, key: K) -> usize {
+        self.rank_bits(key.to_bits())
+    }
+
+    /// Returns the `k`-th smallest key, counting from zero, along with its value.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, ()> = Xfast::new(31).with_order_statistics();
+    ///     for &key in [3, 9, 27].iter() {
+    ///         test_trie.insert(key, ());
+    ///     }
+    ///     assert_eq!(test_trie.select(1), Some((9, &())));
+    ///     assert!(test_trie.select(3).is_none());
+    /// ```
+    pub fn select(&self, k: usize) -> Option<(K, &V)> {
+        if k >= self.len() {
+            return None;
+        }
+        let sizes = match &self.subtree_sizes {
+            Some(sizes) => sizes,
+            None => return self.iter().nth(k),
+        };
+
+        // descend into the subtree holding the k-th leaf
+        let mut k = k;
+        let mut node = self.levels.node(0, 0);
+        while self.nodes[node].level != self.nr_levels {
+            let internal_node = &self.nodes[node];
+            if let Some(left_node) = internal_node.left_child() {
+                let left_size = self.subtree_size(sizes, left_node);
+                if k < left_size {
+                    node = left_node;
+                    continue;
+                }
+                k -= left_size;
+            }
+            node = internal_node.right_child().expect("subtree sizes out of sync");
+        }
+        Some(self.leaf_key_value(node))
+    }
+
+    /// Returns the count of keys in `range`.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, ()> = Xfast::new(31).with_order_statistics();
+    ///     for key in 0..20 {
+    ///         test_trie.insert(key, ());
+    ///     }
+    ///     assert_eq!(test_trie.count_range(5..=9), 5);
+    ///     assert_eq!(test_trie.count_range(18..), 2);
+    /// ```
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        match self.leaf_bounds(range) {
+            None => 0,
+            Some((front, back)) => {
+                if self.subtree_sizes.is_none() {
+                    return XfastRange::<K, V>::new(&self.nodes, &self.values, Some((front, back))).count();
+                }
+                self.rank_bits(self.nodes[back].key) - self.rank_bits(self.nodes[front].key) + 1
+            }
+        }
+    }
+
+    /// Returns a key picked uniformly at random along with its value, None if the trie is empty.
+    ///
+    /// `random` is the caller's source of uniformly distributed 64 bit integers. It can be called
+    /// more than once since values which would bias the pick are rejected.
+    /// # Examples
+    /// ```
+    ///     use xfast::Xfast;
+    ///
+    ///     let mut test_trie: Xfast<usize, ()> = Xfast::new(31).with_order_statistics();
+    ///     for &key in [3, 9, 27].iter() {
+    ///         test_trie.insert(key, ());
+    ///     }
+    ///     let mut seed = 0x2545_f491_4f6c_dd1du64;
+    ///     let random = || {
+    ///         seed ^= seed << 13;
+    ///         seed ^= seed >> 7;
+    ///         seed ^= seed << 17;
+    ///         seed
+    ///     };
+    ///     let (key, _) = test_trie.sample(random).unwrap();
+    ///     assert!(test_trie.contains_key(key));
+    /// ```
+    pub fn sample<F: FnMut() -> u64>(&self, mut random: F) -> Option<(K, &V)> {
+        let len = self.len() as u64;
+        if len == 0 {
+            return None;
+        }
+        // the lowest 2^64 % len values would make some of the keys more likely than others
+        let threshold = len.wrapping_neg() % len;
+        loop {
+            let value = random();
+            if value >= threshold {
+                return self.select((value % len) as usize);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Xfast;
+
+    fn init(order_statistics: bool) -> Xfast<usize, usize> {
+        let mut test_trie = Xfast::new(63);
+        if order_statistics {
+            test_trie = test_trie.with_order_statistics();
+        }
+        for &key in [11, 1, 18, 5, 40, 63, 0].iter() {
+            test_trie.insert(key, key);
+        }
+        test_trie.remove(18);
+        test_trie
+    }
+
+    fn statistics(test_trie: &Xfast<usize, usize>) -> (Vec<usize>, Vec<Option<usize>>, Vec<usize>) {
+        let ranks = [0, 1, 2, 11, 12, 50, 63].iter().map(|&key| test_trie.rank(key)).collect();
+        let selected = (0..7).map(|k| test_trie.select(k).map(|(key, _)| key)).collect();
+        let counts = vec![test_trie.count_range(1..=40), test_trie.count_range(2..11), test_trie.count_range(..)];
+        (ranks, selected, counts)
+    }
+
+    #[test]
+    fn order_statistics() -> Result<(), String> {
+        let expected = (
+            vec![0, 1, 2, 3, 4, 5, 5],
+            vec![Some(0), Some(1), Some(5), Some(11), Some(40), Some(63), None],
+            vec![4, 1, 6],
+        );
+        let tracked = statistics(&init(true));
+        let scanned = statistics(&init(false));
+        if tracked == expected && scanned == expected {
+            Ok(())
+        }
+        else {
+            Err(format!("Order statistics are wrong, got {:?} and {:?}", tracked, scanned))
+        }
+    }
+
+    #[test]
+    fn enabled_later() -> Result<(), String> {
+        let mut test_trie = init(false).with_order_statistics();
+        test_trie.pop_first();
+        test_trie.insert(7, 7);
+        if test_trie.rank(8) == 3 && test_trie.select(2) == Some((7, &7)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Existing keys should be counted when enabled"))
+        }
+    }
+
+    #[test]
+    fn uniform_sample() -> Result<(), String> {
+        let test_trie = init(true);
+        let mut counts = vec![0; test_trie.len()];
+        let mut value = 0u64;
+        for _ in 0..600 {
+            let (key, _) = test_trie.sample(|| { value += 1; value }).unwrap();
+            counts[test_trie.rank(key)] += 1;
+        }
+        if counts.iter().all(|&count| count == 100) {
+            Ok(())
+        }
+        else {
+            Err(format!("Every key should be picked as often, got {:?}", counts))
+        }
+    }
+}
@@ -0,0 +1,553 @@
+//! [van Emde Boas tree](https://en.wikipedia.org/wiki/Van_Emde_Boas_tree) for dense universes.
+//!
+//! A node over a universe of 2^b keys splits every key into its high and low b/2 bits: the high
+//! bits pick a cluster holding the low bits, and a summary tree keeps the set of non-empty
+//! clusters. The minimum of a node is kept out of its clusters, so an empty cluster takes a new
+//! key in O(1) and every operation recurses into only one half of the bits, giving worst-case
+//! O(log log U) time without any hashing. Clusters are only allocated once they hold a key.
+
+use std::iter::FusedIterator;
+
+// universes of up to 2^LEAF_BITS keys are stored as a bitmap
+const LEAF_BITS: u32 = 6;
+// widest universe a tree can be created with
+const MAX_BITS: u32 = 24;
+
+#[derive(Debug)]
+enum VebNode<V> {
+    // bitmap of the keys present along with their values
+    Leaf {
+        keys: u64,
+        values: Box<[Option<V>]>,
+    },
+    Branch(Box<Branch<V>>),
+}
+
+#[derive(Debug)]
+struct Branch<V> {
+    // the smallest key isn't stored in any cluster
+    min: u32,
+    min_value: V,
+    max: u32,
+    // high bits of the non-empty clusters, None when all of them are empty
+    summary: Option<VebNode<()>>,
+    clusters: Box<[Option<VebNode<V>>]>,
+}
+
+// width of the high and low halves of a key of `bits` bits
+fn halves(bits: u32) -> (u32, u32) {
+    (bits - bits / 2, bits / 2)
+}
+
+fn split(bits: u32, key: u32) -> (usize, u32) {
+    let low_bits = bits / 2;
+    ((key >> low_bits) as usize, key & ((1 << low_bits) - 1))
+}
+
+fn join(bits: u32, high: usize, low: u32) -> u32 {
+    ((high as u32) << (bits / 2)) | low
+}
+
+impl<V> VebNode<V> {
+
+    // a node of `bits` bits holding only `key`
+    fn new(bits: u32, key: u32, value: V) -> Self {
+        if bits <= LEAF_BITS {
+            let mut values: Box<[Option<V>]> = (0..1 << bits).map(|_| None).collect();
+            values[key as usize] = Some(value);
+            return VebNode::Leaf { keys: 1 << key, values };
+        }
+        let (high_bits, _) = halves(bits);
+        VebNode::Branch(Box::new(Branch {
+            min: key,
+            min_value: value,
+            max: key,
+            summary: None,
+            clusters: (0..1 << high_bits).map(|_| None).collect(),
+        }))
+    }
+
+    // nodes are never empty, empty clusters are freed
+    fn min(&self) -> u32 {
+        match self {
+            VebNode::Leaf { keys, .. } => keys.trailing_zeros(),
+            VebNode::Branch(branch) => branch.min,
+        }
+    }
+
+    fn max(&self) -> u32 {
+        match self {
+            VebNode::Leaf { keys, .. } => 63 - keys.leading_zeros(),
+            VebNode::Branch(branch) => branch.max,
+        }
+    }
+
+    fn get(&self, bits: u32, key: u32) -> Option<&V> {
+        match self {
+            VebNode::Leaf { values, .. } => values[key as usize].as_ref(),
+            VebNode::Branch(branch) => {
+                if key == branch.min {
+                    return Some(&branch.min_value);
+                }
+                let (high, low) = split(bits, key);
+                branch.clusters[high].as_ref()?.get(bits / 2, low)
+            }
+        }
+    }
+
+    fn get_mut(&mut self, bits: u32, key: u32) -> Option<&mut V> {
+        match self {
+            VebNode::Leaf { values, .. } => values[key as usize].as_mut(),
+            VebNode::Branch(branch) => {
+                if key == branch.min {
+                    return Some(&mut branch.min_value);
+                }
+                let (high, low) = split(bits, key);
+                branch.clusters[high].as_mut()?.get_mut(bits / 2, low)
+            }
+        }
+    }
+
+    fn insert(&mut self, bits: u32, key: u32, value: V) -> Option<V> {
+        let branch = match self {
+            VebNode::Leaf { keys, values } => {
+                *keys |= 1 << key;
+                return values[key as usize].replace(value);
+            }
+            VebNode::Branch(branch) => branch,
+        };
+        if key == branch.min {
+            return Some(std::mem::replace(&mut branch.min_value, value));
+        }
+
+        // a smaller key takes the place of the minimum, which moves down into its cluster
+        let (mut key, mut value) = (key, value);
+        if key < branch.min {
+            std::mem::swap(&mut key, &mut branch.min);
+            std::mem::swap(&mut value, &mut branch.min_value);
+        }
+        if key > branch.max {
+            branch.max = key;
+        }
+        let (high_bits, low_bits) = halves(bits);
+        let (high, low) = split(bits, key);
+        match &mut branch.clusters[high] {
+            Some(cluster) => cluster.insert(low_bits, low, value),
+            // filling an empty cluster is O(1), so only the summary is recursed into
+            cluster @ None => {
+                *cluster = Some(VebNode::new(low_bits, low, value));
+                match &mut branch.summary {
+                    Some(summary) => {
+                        summary.insert(high_bits, high as u32, ());
+                    }
+                    None => branch.summary = Some(VebNode::new(high_bits, high as u32, ())),
+                }
+                None
+            }
+        }
+    }
+
+    // remove `key` from the node in `slot`, freeing the node once it's left empty
+    fn remove_from(slot: &mut Option<VebNode<V>>, bits: u32, key: u32) -> Option<V> {
+        let node = slot.as_mut()?;
+        if node.min() == key && node.max() == key {
+            return match slot.take()? {
+                VebNode::Leaf { mut values, .. } => values[key as usize].take(),
+                VebNode::Branch(branch) => Some(branch.min_value),
+            };
+        }
+        node.remove(bits, key)
+    }
+
+    // remove `key` from a node holding some other key as well
+    fn remove(&mut self, bits: u32, key: u32) -> Option<V> {
+        let branch = match self {
+            VebNode::Leaf { keys, values } => {
+                *keys &= !(1 << key);
+                return values[key as usize].take();
+            }
+            VebNode::Branch(branch) => branch,
+        };
+        let (high_bits, low_bits) = halves(bits);
+
+        if key == branch.min {
+            // the smallest key of the first cluster becomes the new minimum
+            let summary = branch.summary.as_ref().expect("a node with two keys has a cluster");
+            let high = summary.min() as usize;
+            let low = branch.clusters[high].as_ref().expect("summary out of sync").min();
+            let value = Self::remove_from(&mut branch.clusters[high], low_bits, low).expect("cluster holds its minimum");
+            if branch.clusters[high].is_none() {
+                VebNode::remove_from(&mut branch.summary, high_bits, high as u32);
+            }
+            branch.min = join(bits, high, low);
+            return Some(std::mem::replace(&mut branch.min_value, value));
+        }
+
+        let (high, low) = split(bits, key);
+        let value = Self::remove_from(&mut branch.clusters[high], low_bits, low)?;
+        if branch.clusters[high].is_none() {
+            VebNode::remove_from(&mut branch.summary, high_bits, high as u32);
+        }
+        if key == branch.max {
+            branch.max = match &branch.summary {
+                Some(summary) => {
+                    let high = summary.max() as usize;
+                    join(bits, high, branch.clusters[high].as_ref().expect("summary out of sync").max())
+                }
+                None => branch.min,
+            };
+        }
+        Some(value)
+    }
+
+    // smallest key more than or equal to `key`
+    fn successor(&self, bits: u32, key: u32) -> Option<u32> {
+        let branch = match self {
+            VebNode::Leaf { keys, .. } => {
+                let above = keys & (u64::MAX << key);
+                return if above == 0 { None } else { Some(above.trailing_zeros()) };
+            }
+            VebNode::Branch(branch) => branch,
+        };
+        if key <= branch.min {
+            return Some(branch.min);
+        }
+        if key > branch.max {
+            return None;
+        }
+        let (high_bits, low_bits) = halves(bits);
+        let (high, low) = split(bits, key);
+        if let Some(cluster) = &branch.clusters[high] {
+            if low <= cluster.max() {
+                return cluster.successor(low_bits, low).map(|low| join(bits, high, low));
+            }
+        }
+        // the key is at most the maximum, so a later cluster is non-empty
+        let high = branch.summary.as_ref()?.successor(high_bits, high as u32 + 1)? as usize;
+        let cluster = branch.clusters[high].as_ref().expect("summary out of sync");
+        Some(join(bits, high, cluster.min()))
+    }
+
+    // largest key less than or equal to `key`
+    fn predecessor(&self, bits: u32, key: u32) -> Option<u32> {
+        let branch = match self {
+            VebNode::Leaf { keys, .. } => {
+                let below = keys & (u64::MAX >> (63 - key));
+                return if below == 0 { None } else { Some(63 - below.leading_zeros()) };
+            }
+            VebNode::Branch(branch) => branch,
+        };
+        if key >= branch.max {
+            return Some(branch.max);
+        }
+        if key < branch.min {
+            return None;
+        }
+        let (high_bits, low_bits) = halves(bits);
+        let (high, low) = split(bits, key);
+        if let Some(cluster) = &branch.clusters[high] {
+            if low >= cluster.min() {
+                return cluster.predecessor(low_bits, low).map(|low| join(bits, high, low));
+            }
+        }
+        // without an earlier non-empty cluster the minimum is the predecessor
+        let earlier = match (&branch.summary, high) {
+            (Some(summary), high) if high > 0 => summary.predecessor(high_bits, high as u32 - 1),
+            _ => None,
+        };
+        match earlier {
+            Some(high) => {
+                let high = high as usize;
+                let cluster = branch.clusters[high].as_ref().expect("summary out of sync");
+                Some(join(bits, high, cluster.max()))
+            }
+            None => Some(branch.min),
+        }
+    }
+}
+
+/// A van Emde Boas tree mapping keys of a universe of up to 2^24 integers to values.
+///
+/// Unlike [`Xfast`](crate::Xfast) it doesn't rely on hashing: insertions, deletions, lookups and
+/// successor/predecessor queries take O(log log U) time in the worst case. Clusters are allocated
+/// as keys are inserted into them, so sparse key sets don't pay for the whole universe.
+/// # Examples
+/// ```
+///     use xfast::VebTree;
+///
+///     let mut test_tree: VebTree<&str> = VebTree::new(1 << 20);
+///     test_tree.insert(11, "eleven");
+///     test_tree.insert(1, "one");
+///     test_tree.insert(5, "five");
+///     assert_eq!(test_tree.len(), 3);
+///
+///     assert_eq!(test_tree.predecessor(3), Some((1, &"one")));
+///     assert_eq!(test_tree.successor(6), Some((11, &"eleven")));
+/// ```
+#[derive(Debug)]
+pub struct VebTree<V> {
+    root: Option<VebNode<V>>,
+    // width of the keys of the universe
+    bits: u32,
+    len: usize,
+}
+
+impl<V> VebTree<V> {
+
+    /// Creates a new tree to store keys from zero up to `range`
+    ///
+    /// # Panics
+    /// Panics if `range` is 2^24 or more.
+    pub fn new(range: u32) -> Self {
+        assert!(range >> MAX_BITS == 0, "range of the tree is limited to 2^24 keys");
+        VebTree {
+            root: None,
+            bits: (32 - range.leading_zeros()).max(1),
+            len: 0,
+        }
+    }
+
+    fn max_key(&self) -> u32 {
+        u32::MAX >> (32 - self.bits)
+    }
+
+    /// Returns the count of values stored in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the tree stores no values
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the value stored with `key`
+    pub fn get(&self, key: u32) -> Option<&V> {
+        if key > self.max_key() {
+            return None;
+        }
+        self.root.as_ref()?.get(self.bits, key)
+    }
+
+    /// Returns a mutable reference to the value stored with `key`
+    pub fn get_mut(&mut self, key: u32) -> Option<&mut V> {
+        if key > self.max_key() {
+            return None;
+        }
+        self.root.as_mut()?.get_mut(self.bits, key)
+    }
+
+    /// Returns true if the tree stores a value for `key`
+    pub fn contains_key(&self, key: u32) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Insert `key` and `value` into the tree. If the key was already present its value is replaced
+    /// and the previous value is returned, otherwise it returns None.
+    /// # Examples
+    /// ```
+    ///     use xfast::VebTree;
+    ///
+    ///     let mut test_tree: VebTree<&str> = VebTree::new(31);
+    ///     assert!(test_tree.insert(11, "eleven").is_none());
+    ///     assert_eq!(test_tree.insert(11, "xi"), Some("eleven"));
+    ///     assert_eq!(test_tree.len(), 1);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `key` is larger than the range the tree was created with.
+    pub fn insert(&mut self, key: u32, value: V) -> Option<V> {
+        assert!(key <= self.max_key(), "key out of the range of the tree");
+        let previous = match &mut self.root {
+            Some(root) => root.insert(self.bits, key, value),
+            None => {
+                self.root = Some(VebNode::new(self.bits, key, value));
+                None
+            }
+        };
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    /// Delete a key from the tree. If the key doesn't exist it returns None else returns the value
+    /// that was stored with it.
+    pub fn remove(&mut self, key: u32) -> Option<V> {
+        if key > self.max_key() {
+            return None;
+        }
+        let value = VebNode::remove_from(&mut self.root, self.bits, key)?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn successor_key(&self, key: u32) -> Option<u32> {
+        if key > self.max_key() {
+            return None;
+        }
+        self.root.as_ref()?.successor(self.bits, key)
+    }
+
+    fn predecessor_key(&self, key: u32) -> Option<u32> {
+        self.root.as_ref()?.predecessor(self.bits, key.min(self.max_key()))
+    }
+
+    fn key_value(&self, key: u32) -> (u32, &V) {
+        (key, self.get(key).expect("tree holds the key it found"))
+    }
+
+    /// Returns the smallest key more than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn successor(&self, key: u32) -> Option<(u32, &V)> {
+        self.successor_key(key).map(|key| self.key_value(key))
+    }
+
+    /// Returns the largest key less than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn predecessor(&self, key: u32) -> Option<(u32, &V)> {
+        self.predecessor_key(key).map(|key| self.key_value(key))
+    }
+
+    /// Returns the smallest key of the tree along with its value
+    pub fn first_key_value(&self) -> Option<(u32, &V)> {
+        self.root.as_ref().map(|root| self.key_value(root.min()))
+    }
+
+    /// Returns the largest key of the tree along with its value
+    pub fn last_key_value(&self) -> Option<(u32, &V)> {
+        self.root.as_ref().map(|root| self.key_value(root.max()))
+    }
+
+    /// Returns an iterator around all the key-value pairs stored in the tree, in ascending order of keys.
+    /// # Examples
+    /// ```
+    ///     use xfast::VebTree;
+    ///
+    ///     let mut test_tree: VebTree<()> = VebTree::new(1023);
+    ///     for key in (0..1000).rev() {
+    ///         test_tree.insert(key, ());
+    ///     }
+    ///     assert!(test_tree.iter().map(|(key, _)| key).eq(0..1000));
+    /// ```
+    pub fn iter(&self) -> VebIter<'_, V> {
+        VebIter {
+            tree: self,
+            front: self.root.as_ref().map(VebNode::min),
+            back: self.root.as_ref().map(VebNode::max),
+            len: self.len,
+        }
+    }
+}
+
+impl<V> Default for VebTree<V> {
+    /// Creates an empty tree spanning the widest supported universe
+    fn default() -> Self {
+        Self::new((1 << MAX_BITS) - 1)
+    }
+}
+
+/// Double-ended iterator around the VebTree key and value pairs in ascending order of keys
+pub struct VebIter<'a, V> {
+    tree: &'a VebTree<V>,
+    // next keys to be yielded from either end
+    front: Option<u32>,
+    back: Option<u32>,
+    // count of pairs left to be yielded
+    len: usize,
+}
+
+impl<'a, V> Iterator for VebIter<'a, V> {
+    type Item = (u32, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let key = self.front?;
+        self.len -= 1;
+        self.front = key.checked_add(1).and_then(|next| self.tree.successor_key(next));
+        Some(self.tree.key_value(key))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, V> DoubleEndedIterator for VebIter<'a, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let key = self.back?;
+        self.len -= 1;
+        self.back = key.checked_sub(1).and_then(|previous| self.tree.predecessor_key(previous));
+        Some(self.tree.key_value(key))
+    }
+}
+
+impl<'a, V> ExactSizeIterator for VebIter<'a, V> {}
+
+impl<'a, V> FusedIterator for VebIter<'a, V> {}
+
+impl<'a, V> IntoIterator for &'a VebTree<V> {
+    type Item = (u32, &'a V);
+    type IntoIter = VebIter<'a, V>;
+    fn into_iter(self) -> VebIter<'a, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VebTree;
+
+    fn init() -> VebTree<u32> {
+        let mut test_tree = VebTree::new((1 << 16) - 1);
+        for key in (0..1 << 16).filter(|key| key % 7 == 3) {
+            test_tree.insert(key, key);
+        }
+        test_tree
+    }
+
+    #[test]
+    fn neighbours() -> Result<(), String> {
+        let test_tree = init();
+        if test_tree.successor(4) == Some((10, &10)) && test_tree.predecessor(9) == Some((3, &3))
+            && test_tree.predecessor(2).is_none() && test_tree.successor(65531).is_none()
+            && test_tree.first_key_value() == Some((3, &3)) && test_tree.last_key_value() == Some((65530, &65530)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Successor or predecessor is wrong"))
+        }
+    }
+
+    #[test]
+    fn remove_minimum() -> Result<(), String> {
+        let mut test_tree = init();
+        let removed: Vec<Option<u32>> = [3, 10, 65530, 3].iter().map(|&key| test_tree.remove(key)).collect();
+        if removed == vec![Some(3), Some(10), Some(65530), None]
+            && test_tree.first_key_value() == Some((17, &17)) && test_tree.last_key_value() == Some((65523, &65523))
+            && test_tree.len() == 9359 {
+            Ok(())
+        }
+        else {
+            Err(format!("Removing the extremes is wrong, got {:?}", removed))
+        }
+    }
+
+    #[test]
+    fn remove_all() -> Result<(), String> {
+        let mut test_tree = init();
+        for key in (0..1 << 16).rev() {
+            test_tree.remove(key);
+        }
+        test_tree.insert(500, 1);
+        if test_tree.len() == 1 && test_tree.iter().eq(Some((500, &1))) && test_tree.predecessor(u32::MAX) == Some((500, &1)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("The tree should be reusable once emptied"))
+        }
+    }
+}
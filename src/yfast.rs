@@ -0,0 +1,323 @@
+//! [Y-fast Trie](https://en.wikipedia.org/wiki/Y-fast_trie) storing integers in linear space.
+//!
+//! The keys are split into buckets of about log U consecutive keys. Only a representative of every
+//! bucket is stored in an X-fast trie, which brings the space down from O(n log U) to O(n), while
+//! every bucket is a balanced search tree small enough to be searched in O(log log U).
+
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use crate::{Xfast, XfastIter, XfastKey};
+
+/// A bitwise trie storing integers in linear space.
+///
+/// Representative keys are kept in an [`Xfast`] trie while the rest of the keys are stored in
+/// balanced buckets of about log U keys each. Every bucket holds the keys from its representative
+/// up to the representative of the next bucket, and buckets are split or merged as they grow or
+/// shrink. Lookups, insertions, deletions and successor/predecessor queries run in O(log log U)
+/// amortized time.
+/// # Examples
+/// ```
+///     use xfast::Yfast;
+///
+///     let mut test_trie: Yfast<u64, &str> = Yfast::default();
+///     test_trie.insert(11, "eleven");
+///     test_trie.insert(1, "one");
+///     test_trie.insert(5, "five");
+///     assert_eq!(test_trie.len(), 3);
+///
+///     assert_eq!(test_trie.predecessor(3), Some((1, &"one")));
+///     assert_eq!(test_trie.successor(6), Some((11, &"eleven")));
+/// ```
+#[derive(Debug)]
+pub struct Yfast<K, V> {
+    // buckets keyed by the smallest key they may hold; the first one starts at the smallest key
+    buckets: Xfast<K, BTreeMap<K, V>>,
+    // count of keys stored across the buckets
+    len: usize,
+    // buckets are kept between half and twice this size
+    bucket_size: usize,
+}
+
+impl<K: XfastKey, V> Yfast<K, V> {
+
+    /// Creates a new Y-fast trie to store keys from zero up to `range`
+    /// # Examples
+    /// ```
+    /// # #![allow(unused_mut)]
+    ///     use xfast::Yfast;
+    ///
+    ///     let mut test_trie: Yfast<u32, &str> = Yfast::new(1 << 20);
+    /// ```
+    pub fn new(range: K) -> Self {
+        Self::with_buckets(Xfast::new(range))
+    }
+
+    fn with_buckets(buckets: Xfast<K, BTreeMap<K, V>>) -> Self {
+        let bucket_size = buckets.nr_levels;
+        Yfast {
+            buckets,
+            len: 0,
+            bucket_size,
+        }
+    }
+
+    /// Returns the count of values stored in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the trie stores no values
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // bucket which may hold `key`
+    fn bucket(&self, key: K) -> Option<(K, &BTreeMap<K, V>)> {
+        self.buckets.predecessor(key)
+    }
+
+    /// Returns a reference to the value stored with `key`
+    pub fn get(&self, key: K) -> Option<&V> {
+        self.bucket(key)?.1.get(&key)
+    }
+
+    /// Returns a mutable reference to the value stored with `key`
+    pub fn get_mut(&mut self, key: K) -> Option<&mut V> {
+        self.buckets.predecessor_mut(key)?.1.get_mut(&key)
+    }
+
+    /// Returns true if the trie stores a value for `key`
+    pub fn contains_key(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Insert `key` and `value` into the trie. If the key was already present its value is replaced
+    /// and the previous value is returned, otherwise it returns None.
+    /// # Examples
+    /// ```
+    ///     use xfast::Yfast;
+    ///
+    ///     let mut test_trie: Yfast<u32, &str> = Yfast::new(31);
+    ///     assert!(test_trie.insert(11, "eleven").is_none());
+    ///     assert_eq!(test_trie.insert(11, "xi"), Some("eleven"));
+    ///     assert_eq!(test_trie.len(), 1);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `key` is larger than the range the trie was created with.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        assert!(key.to_bits() <= self.buckets.max_key(), "key out of the range of the trie");
+        if self.buckets.is_empty() {
+            self.buckets.insert(K::from_bits(0), BTreeMap::new());
+        }
+
+        let (start, bucket) = self.buckets.predecessor_mut(key).expect("first bucket starts at the smallest key");
+        let previous = bucket.insert(key, value);
+        if previous.is_none() {
+            self.len += 1;
+            self.split_bucket(start);
+        }
+        previous
+    }
+
+    /// Delete a key from the trie. If the key doesn't exist it returns None else returns the value
+    /// that was stored with it.
+    /// # Examples
+    /// ```
+    ///     use xfast::Yfast;
+    ///
+    ///     let mut test_trie: Yfast<u32, &str> = Yfast::new(31);
+    ///     test_trie.insert(11, "eleven");
+    ///     assert_eq!(test_trie.remove(11), Some("eleven"));
+    ///     assert!(test_trie.remove(11).is_none());
+    /// ```
+    pub fn remove(&mut self, key: K) -> Option<V> {
+        let (start, bucket) = self.buckets.predecessor_mut(key)?;
+        let value = bucket.remove(&key)?;
+        self.len -= 1;
+        if bucket.len() < (self.bucket_size / 2).max(1) {
+            self.merge_bucket(start);
+        }
+        Some(value)
+    }
+
+    // split the bucket starting at `start` in halves once it holds more than twice the bucket size
+    fn split_bucket(&mut self, start: K) {
+        let bucket = self.buckets.get_mut(start).expect("bucket to split");
+        if bucket.len() <= 2 * self.bucket_size {
+            return;
+        }
+        let middle = *bucket.keys().nth(bucket.len() / 2).expect("bucket holds its middle key");
+        let upper_half = bucket.split_off(&middle);
+        self.buckets.insert(middle, upper_half);
+    }
+
+    // merge the underfull bucket starting at `start` with a neighbouring bucket
+    fn merge_bucket(&mut self, start: K) {
+        let previous = self.buckets.range(..start).next_back().map(|(previous, _)| previous);
+        match previous {
+            // fold the bucket into the one before it
+            Some(previous) => {
+                let mut bucket = self.buckets.remove(start).expect("bucket to merge");
+                self.buckets.get_mut(previous).expect("previous bucket").append(&mut bucket);
+                self.split_bucket(previous);
+            }
+            // the first bucket keeps its start and takes over the next bucket
+            None => {
+                let next = self.buckets.range((Bound::Excluded(start), Bound::Unbounded)).next().map(|(next, _)| next);
+                if let Some(next) = next {
+                    let mut bucket = self.buckets.remove(next).expect("next bucket");
+                    self.buckets.get_mut(start).expect("first bucket").append(&mut bucket);
+                    self.split_bucket(start);
+                }
+            }
+        }
+    }
+
+    /// Returns the smallest key more than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn successor(&self, key: K) -> Option<(K, &V)> {
+        let (start, bucket) = self.bucket(key)?;
+        if let Some((&found, value)) = bucket.range(key..).next() {
+            return Some((found, value));
+        }
+        // only the first bucket of an empty trie is left empty, so the next bucket isn't
+        let mut next_buckets = self.buckets.range((Bound::Excluded(start), Bound::Unbounded));
+        let (_, next_bucket) = next_buckets.next()?;
+        next_bucket.iter().next().map(|(&found, value)| (found, value))
+    }
+
+    /// Returns the largest key less than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn predecessor(&self, key: K) -> Option<(K, &V)> {
+        let (start, bucket) = self.bucket(key)?;
+        if let Some((&found, value)) = bucket.range(..=key).next_back() {
+            return Some((found, value));
+        }
+        let (_, previous_bucket) = self.buckets.range(..start).next_back()?;
+        previous_bucket.iter().next_back().map(|(&found, value)| (found, value))
+    }
+
+    /// Returns the smallest key of the trie along with its value
+    pub fn first_key_value(&self) -> Option<(K, &V)> {
+        let (_, bucket) = self.buckets.first_key_value()?;
+        bucket.iter().next().map(|(&key, value)| (key, value))
+    }
+
+    /// Returns the largest key of the trie along with its value
+    pub fn last_key_value(&self) -> Option<(K, &V)> {
+        let (_, bucket) = self.buckets.last_key_value()?;
+        bucket.iter().next_back().map(|(&key, value)| (key, value))
+    }
+
+    /// Returns an iterator around all the key-value pairs stored in the trie, in ascending order of keys.
+    /// # Examples
+    /// ```
+    ///     use xfast::Yfast;
+    ///
+    ///     let mut test_trie: Yfast<u32, ()> = Yfast::new(1023);
+    ///     for key in (0..1000).rev() {
+    ///         test_trie.insert(key, ());
+    ///     }
+    ///     assert!(test_trie.iter().map(|(key, _)| key).eq(0..1000));
+    /// ```
+    pub fn iter(&self) -> YfastIter<'_, K, V> {
+        YfastIter {
+            buckets: self.buckets.iter(),
+            bucket: None,
+        }
+    }
+}
+
+impl<K: XfastKey, V> Default for Yfast<K, V> {
+    /// Creates an empty trie spanning the full width of the key type
+    fn default() -> Self {
+        Self::with_buckets(Xfast::default())
+    }
+}
+
+/// Iterator around the Yfast key and value pairs in ascending order of keys
+pub struct YfastIter<'a, K, V> {
+    buckets: XfastIter<'a, K, BTreeMap<K, V>>,
+    // pairs left in the bucket being walked
+    bucket: Option<btree_map::Iter<'a, K, V>>,
+}
+
+impl<'a, K: XfastKey, V> Iterator for YfastIter<'a, K, V> {
+    type Item = (K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((&key, value)) = self.bucket.as_mut().and_then(Iterator::next) {
+                return Some((key, value));
+            }
+            let (_, bucket) = self.buckets.next()?;
+            self.bucket = Some(bucket.iter());
+        }
+    }
+}
+
+impl<'a, K: XfastKey, V> IntoIterator for &'a Yfast<K, V> {
+    type Item = (K, &'a V);
+    type IntoIter = YfastIter<'a, K, V>;
+    fn into_iter(self) -> YfastIter<'a, K, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Yfast;
+
+    fn init() -> Yfast<u32, u32> {
+        let mut test_trie = Yfast::new(1023);
+        for key in (0..1024).filter(|key| key % 3 == 0) {
+            test_trie.insert(key, key);
+        }
+        test_trie
+    }
+
+    #[test]
+    fn buckets_stay_balanced() -> Result<(), String> {
+        let mut test_trie = init();
+        for key in (0..1024).filter(|key| key % 6 == 0) {
+            test_trie.remove(key);
+        }
+        let bucket_size = test_trie.bucket_size;
+        let sizes: Vec<usize> = test_trie.buckets.iter().map(|(_, bucket)| bucket.len()).collect();
+        if sizes.iter().all(|&size| size >= bucket_size / 2 && size <= 2 * bucket_size) && test_trie.len() == 171 {
+            Ok(())
+        }
+        else {
+            Err(format!("Bucket sizes are out of bounds, got {:?}", sizes))
+        }
+    }
+
+    #[test]
+    fn neighbours() -> Result<(), String> {
+        let test_trie = init();
+        if test_trie.successor(100) == Some((102, &102)) && test_trie.predecessor(100) == Some((99, &99))
+            && test_trie.successor(1022) == Some((1023, &1023)) && test_trie.predecessor(0) == Some((0, &0))
+            && test_trie.first_key_value() == Some((0, &0)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("Successor or predecessor is wrong"))
+        }
+    }
+
+    #[test]
+    fn remove_all() -> Result<(), String> {
+        let mut test_trie = init();
+        for key in 0..1024 {
+            test_trie.remove(key);
+        }
+        test_trie.insert(500, 1);
+        if test_trie.len() == 1 && test_trie.predecessor(1023) == Some((500, &1)) && test_trie.successor(0) == Some((500, &1)) {
+            Ok(())
+        }
+        else {
+            Err(String::from("The trie should be reusable once emptied"))
+        }
+    }
+}
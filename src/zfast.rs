@@ -0,0 +1,741 @@
+//! [Z-fast Trie](https://arxiv.org/abs/1804.04720) storing variable-length byte strings.
+//!
+//! Keys are compared as bit strings in which every byte is preceded by a set bit and the key is
+//! closed by a cleared bit. No key is then a prefix of another one, and the order of the bit
+//! strings is the lexicographic order of the byte strings.
+//!
+//! The trie is a compacted binary trie. Every internal node is stored in a hash map, one per length,
+//! under its handle: the prefix of its extent whose length is the 2-fattest number (the one
+//! divisible by the highest power of two) of the node's skip interval. A fat binary search over
+//! those lengths finds the node where a key leaves the trie with O(log L) lookups, L being the
+//! length of the key in bits. Every internal node also keeps the smallest and largest leaves below
+//! it, the neighbours of a key are then read off the node where it leaves the trie.
+
+use std::collections::HashMap;
+use std::iter::FusedIterator;
+
+use crate::arena::Arena;
+use crate::Node;
+
+// bits in the encoding of `key`
+fn bits_len(key: &[u8]) -> usize {
+    9 * key.len() + 1
+}
+
+// bit at `index` of the encoding of `key`
+fn bit(key: &[u8], index: usize) -> bool {
+    let (byte, offset) = (index / 9, index % 9);
+    if offset == 0 {
+        byte < key.len()
+    }
+    else {
+        (key[byte] >> (8 - offset)) & 1 != 0
+    }
+}
+
+// length of the longest common prefix of the encodings of `a` and `b`
+fn lcp(a: &[u8], b: &[u8]) -> usize {
+    match a.iter().zip(b).position(|(x, y)| x != y) {
+        Some(byte) => 9 * byte + 1 + (a[byte] ^ b[byte]).leading_zeros() as usize,
+        None if a.len() == b.len() => bits_len(a),
+        None => 9 * a.len().min(b.len()),
+    }
+}
+
+// number of [low, high] divisible by the highest power of two
+fn two_fattest(low: usize, high: usize) -> usize {
+    if low == 0 {
+        return 0;
+    }
+    let differing = usize::BITS - 1 - ((low - 1) ^ high).leading_zeros();
+    high & (usize::MAX << differing)
+}
+
+// write the bytes identifying the first `len` bits of the encoding of `key` to `prefix`,
+// false if those bits close the key, as no handle does
+fn prefix_bytes(key: &[u8], len: usize, prefix: &mut Vec<u8>) -> bool {
+    let (byte, offset) = (len / 9, len % 9);
+    prefix.clear();
+    if offset != 0 && byte == key.len() {
+        return false;
+    }
+    prefix.extend_from_slice(&key[..byte]);
+    if offset != 0 {
+        // keep the bits of the partial byte following its leading set bit
+        prefix.push(key[byte] & !(0xff >> (offset - 1)));
+    }
+    true
+}
+
+// Nodes of the trie
+#[derive(Debug)]
+struct ZfastNode<V> {
+    // bits of the extent, the prefix shared by every key below the node
+    extent_len: usize,
+    // bits of the name, the extent of the parent and the bit leading to the node
+    name_len: usize,
+    parent: Option<Node>,
+    kind: NodeKind<V>,
+}
+
+#[derive(Debug)]
+enum NodeKind<V> {
+    Internal {
+        children: [Node; 2],
+        // a leaf below the node, whose key starts with the node's extent
+        reference: Node,
+        // smallest and largest leaves below the node
+        extremes: [Node; 2],
+    },
+    Leaf(ZfastLeaf<V>),
+}
+
+#[derive(Debug)]
+struct ZfastLeaf<V> {
+    key: Box<[u8]>,
+    value: V,
+    // internal node referencing the leaf, all the leaves but one are referenced once
+    owner: Option<Node>,
+    // neighbouring leaves in the order of keys
+    prev: Option<Node>,
+    next: Option<Node>,
+}
+
+/// A compacted binary trie storing byte strings in lexicographic order.
+///
+/// Lookups, insertions, deletions and successor and predecessor queries locate the key with O(log L)
+/// hash map lookups, L being the length of the key. Insertions and deletions also update the
+/// smallest or largest leaf of the ancestors sharing the leaf added or removed.
+/// # Examples
+/// ```
+///     use xfast::ZfastTrie;
+///
+///     let mut test_trie: ZfastTrie<u32> = ZfastTrie::new();
+///     test_trie.insert(b"/index.html", 1);
+///     test_trie.insert(b"/docs/intro", 2);
+///     test_trie.insert(b"/docs", 3);
+///
+///     assert_eq!(test_trie.successor(b"/docs/"), Some((&b"/docs/intro"[..], &2)));
+///     assert_eq!(test_trie.predecessor(b"/docs/"), Some((&b"/docs"[..], &3)));
+///     assert_eq!(test_trie.longest_common_prefix(b"/docs/api"), b"/docs/");
+/// ```
+#[derive(Debug)]
+pub struct ZfastTrie<V> {
+    // internal nodes by their handle, one map per handle length in bits
+    handle_maps: Vec<HashMap<Box<[u8]>, Node>>,
+    nodes: Arena<ZfastNode<V>>,
+    root: Option<Node>,
+    // ends of the linked list of leaves
+    first_leaf: Option<Node>,
+    last_leaf: Option<Node>,
+    len: usize,
+}
+
+impl<V> ZfastTrie<V> {
+
+    /// Creates an empty Z-fast trie
+    pub fn new() -> Self {
+        ZfastTrie {
+            handle_maps: Vec::new(),
+            nodes: Arena::new(),
+            root: None,
+            first_leaf: None,
+            last_leaf: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the count of values stored in the trie
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the trie stores no values
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn leaf(&self, node: Node) -> &ZfastLeaf<V> {
+        match &self.nodes[node].kind {
+            NodeKind::Leaf(leaf) => leaf,
+            NodeKind::Internal { .. } => panic!("expected a leaf"),
+        }
+    }
+
+    fn leaf_mut(&mut self, node: Node) -> &mut ZfastLeaf<V> {
+        match &mut self.nodes[node].kind {
+            NodeKind::Leaf(leaf) => leaf,
+            NodeKind::Internal { .. } => panic!("expected a leaf"),
+        }
+    }
+
+    fn is_leaf(&self, node: Node) -> bool {
+        matches!(self.nodes[node].kind, NodeKind::Leaf(_))
+    }
+
+    fn key_value(&self, node: Node) -> (&[u8], &V) {
+        let leaf = self.leaf(node);
+        (&leaf.key, &leaf.value)
+    }
+
+    // a key starting with the extent of `node`
+    fn extent_key(&self, node: Node) -> &[u8] {
+        match &self.nodes[node].kind {
+            NodeKind::Internal { reference, .. } => &self.leaf(*reference).key,
+            NodeKind::Leaf(leaf) => &leaf.key,
+        }
+    }
+
+    fn child(&self, node: Node, right: bool) -> Node {
+        match &self.nodes[node].kind {
+            NodeKind::Internal { children, .. } => children[right as usize],
+            NodeKind::Leaf(_) => panic!("leaves have no children"),
+        }
+    }
+
+    fn replace_child(&mut self, node: Node, old_child: Node, new_child: Node) {
+        if let NodeKind::Internal { children, .. } = &mut self.nodes[node].kind {
+            let side = if children[0] == old_child { 0 } else { 1 };
+            children[side] = new_child;
+        }
+    }
+
+    fn leftmost_leaf(&self, node: Node) -> Node {
+        match &self.nodes[node].kind {
+            NodeKind::Internal { extremes, .. } => extremes[0],
+            NodeKind::Leaf(_) => node,
+        }
+    }
+
+    fn rightmost_leaf(&self, node: Node) -> Node {
+        match &self.nodes[node].kind {
+            NodeKind::Internal { extremes, .. } => extremes[1],
+            NodeKind::Leaf(_) => node,
+        }
+    }
+
+    // make `new_leaf` the smallest (or largest, if `right`) leaf of `node` and its ancestors in
+    // place of `old_leaf`. Those sharing `old_leaf` run up from `node` without a break.
+    fn replace_extreme(&mut self, mut node: Option<Node>, right: bool, old_leaf: Node, new_leaf: Node) {
+        while let Some(ancestor) = node {
+            match &mut self.nodes[ancestor].kind {
+                NodeKind::Internal { extremes, .. } if extremes[right as usize] == old_leaf => {
+                    extremes[right as usize] = new_leaf;
+                }
+                _ => break,
+            }
+            node = self.nodes[ancestor].parent;
+        }
+    }
+
+    // length and bytes of the handle of the internal `node`
+    fn handle(&self, node: Node) -> (usize, Box<[u8]>) {
+        let handle_len = two_fattest(self.nodes[node].name_len, self.nodes[node].extent_len);
+        let mut handle = Vec::new();
+        prefix_bytes(self.extent_key(node), handle_len, &mut handle);
+        (handle_len, handle.into_boxed_slice())
+    }
+
+    fn add_handle(&mut self, node: Node) {
+        let (handle_len, handle) = self.handle(node);
+        if self.handle_maps.len() <= handle_len {
+            self.handle_maps.resize_with(handle_len + 1, HashMap::new);
+        }
+        self.handle_maps[handle_len].insert(handle, node);
+    }
+
+    fn remove_handle(&mut self, node: Node) {
+        let (handle_len, handle) = self.handle(node);
+        self.handle_maps[handle_len].remove(&handle);
+    }
+
+    // node where `key` leaves the trie, along with the length of the longest prefix of `key`
+    // shared with any key of the trie
+    fn exit_node(&self, key: &[u8]) -> Option<(Node, usize)> {
+        let root = self.root?;
+
+        // fat binary search for the deepest internal node whose extent is a prefix of the key,
+        // which can also end on the exit node itself. Every node on the way has its handle
+        // length as the 2-fattest number of the interval left to search.
+        let mut prefix = Vec::new();
+        let mut found = None;
+        let (mut low, mut high) = (-1, bits_len(key) as isize);
+        while low < high {
+            let handle_len = two_fattest((low + 1) as usize, high as usize);
+            let node = if prefix_bytes(key, handle_len, &mut prefix) {
+                self.handle_maps.get(handle_len).and_then(|handles| handles.get(&prefix[..]))
+            }
+            else {
+                None
+            };
+            match node {
+                Some(&node) => {
+                    low = self.nodes[node].extent_len as isize;
+                    found = Some(node);
+                }
+                None => high = handle_len as isize - 1,
+            }
+        }
+
+        let exit = match found {
+            // without internal nodes the root is a leaf
+            None => root,
+            Some(node) => {
+                let extent_len = self.nodes[node].extent_len;
+                if lcp(key, self.extent_key(node)) >= extent_len {
+                    self.child(node, bit(key, extent_len))
+                }
+                else {
+                    node
+                }
+            }
+        };
+        Some((exit, lcp(key, self.extent_key(exit)).min(self.nodes[exit].extent_len)))
+    }
+
+    // leaf holding `key`
+    fn leaf_node(&self, key: &[u8]) -> Option<Node> {
+        match self.exit_node(key)? {
+            (node, prefix_len) if prefix_len == bits_len(key) => Some(node),
+            _ => None,
+        }
+    }
+
+    /// Returns a reference to the value stored with `key`
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        self.leaf_node(key).map(|node| &self.leaf(node).value)
+    }
+
+    /// Returns a mutable reference to the value stored with `key`
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        let node = self.leaf_node(key)?;
+        Some(&mut self.leaf_mut(node).value)
+    }
+
+    /// Returns true if the trie stores a value for `key`
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.leaf_node(key).is_some()
+    }
+
+    /// Returns the longest prefix of `key` shared with any of the keys of the trie.
+    pub fn longest_common_prefix<'k>(&self, key: &'k [u8]) -> &'k [u8] {
+        match self.exit_node(key) {
+            Some((_, prefix_len)) => &key[..prefix_len / 9],
+            None => &key[..0],
+        }
+    }
+
+    /// Insert `key` and `value` into the trie. If the key was already present its value is replaced
+    /// and the previous value is returned, otherwise it returns None.
+    /// # Examples
+    /// ```
+    ///     use xfast::ZfastTrie;
+    ///
+    ///     let mut test_trie: ZfastTrie<&str> = ZfastTrie::new();
+    ///     assert!(test_trie.insert(b"eleven", "eleven").is_none());
+    ///     assert_eq!(test_trie.insert(b"eleven", "xi"), Some("eleven"));
+    ///     assert_eq!(test_trie.len(), 1);
+    /// ```
+    pub fn insert(&mut self, key: &[u8], value: V) -> Option<V> {
+        let (exit, prefix_len) = match self.exit_node(key) {
+            Some(found) => found,
+            None => {
+                let leaf = self.nodes.alloc(ZfastNode {
+                    extent_len: bits_len(key),
+                    name_len: 0,
+                    parent: None,
+                    kind: NodeKind::Leaf(ZfastLeaf { key: key.into(), value, owner: None, prev: None, next: None }),
+                });
+                self.root = Some(leaf);
+                self.first_leaf = Some(leaf);
+                self.last_leaf = Some(leaf);
+                self.len = 1;
+                return None;
+            }
+        };
+        if prefix_len == bits_len(key) {
+            return Some(std::mem::replace(&mut self.leaf_mut(exit).value, value));
+        }
+
+        // the key goes before or after all the keys below the exit node
+        let right = bit(key, prefix_len);
+        let (prev, next) = if right {
+            let prev = self.rightmost_leaf(exit);
+            (Some(prev), self.leaf(prev).next)
+        }
+        else {
+            let next = self.leftmost_leaf(exit);
+            (self.leaf(next).prev, Some(next))
+        };
+
+        // split the exit node at the end of the common prefix
+        let exit_is_internal = !self.is_leaf(exit);
+        if exit_is_internal {
+            self.remove_handle(exit);
+        }
+        let parent = self.nodes[exit].parent;
+        let leaf = self.nodes.alloc(ZfastNode {
+            extent_len: bits_len(key),
+            name_len: prefix_len + 1,
+            parent: None,
+            kind: NodeKind::Leaf(ZfastLeaf { key: key.into(), value, owner: None, prev, next }),
+        });
+        let (children, extremes) = if right {
+            ([exit, leaf], [self.leftmost_leaf(exit), leaf])
+        }
+        else {
+            ([leaf, exit], [leaf, self.rightmost_leaf(exit)])
+        };
+        let internal = self.nodes.alloc(ZfastNode {
+            extent_len: prefix_len,
+            name_len: self.nodes[exit].name_len,
+            parent,
+            kind: NodeKind::Internal { children, reference: leaf, extremes },
+        });
+        self.nodes[leaf].parent = Some(internal);
+        self.leaf_mut(leaf).owner = Some(internal);
+        self.nodes[exit].parent = Some(internal);
+        self.nodes[exit].name_len = prefix_len + 1;
+        if exit_is_internal {
+            self.add_handle(exit);
+        }
+        self.add_handle(internal);
+        match parent {
+            Some(parent) => self.replace_child(parent, exit, internal),
+            None => self.root = Some(internal),
+        }
+        // the new leaf is next to the leaf which was the largest (or smallest) below the exit node
+        if right {
+            self.replace_extreme(parent, true, prev.expect("leaves below the exit node"), leaf);
+        }
+        else {
+            self.replace_extreme(parent, false, next.expect("leaves below the exit node"), leaf);
+        }
+
+        match prev {
+            Some(prev) => self.leaf_mut(prev).next = Some(leaf),
+            None => self.first_leaf = Some(leaf),
+        }
+        match next {
+            Some(next) => self.leaf_mut(next).prev = Some(leaf),
+            None => self.last_leaf = Some(leaf),
+        }
+        self.len += 1;
+        None
+    }
+
+    /// Delete a key from the trie. If the key doesn't exist it returns None else returns the value
+    /// that was stored with it.
+    /// # Examples
+    /// ```
+    ///     use xfast::ZfastTrie;
+    ///
+    ///     let mut test_trie: ZfastTrie<&str> = ZfastTrie::new();
+    ///     test_trie.insert(b"eleven", "eleven");
+    ///     assert_eq!(test_trie.remove(b"eleven"), Some("eleven"));
+    ///     assert!(test_trie.remove(b"eleven").is_none());
+    /// ```
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let leaf = self.leaf_node(key)?;
+
+        // the sibling of the leaf takes the place of their parent
+        if let Some(parent) = self.nodes[leaf].parent {
+            let sibling = self.child(parent, self.child(parent, false) == leaf);
+            let sibling_is_internal = !self.is_leaf(sibling);
+            self.remove_handle(parent);
+            if sibling_is_internal {
+                self.remove_handle(sibling);
+            }
+            let grandparent = self.nodes[parent].parent;
+            self.nodes[sibling].parent = grandparent;
+            self.nodes[sibling].name_len = self.nodes[parent].name_len;
+            if sibling_is_internal {
+                self.add_handle(sibling);
+            }
+            match grandparent {
+                Some(grandparent) => self.replace_child(grandparent, parent, sibling),
+                None => self.root = Some(sibling),
+            }
+            // the neighbour of the leaf in the sibling takes its place as the smallest (or largest)
+            // leaf of the ancestors
+            if let Some(next) = self.leaf(leaf).next.filter(|_| self.leftmost_leaf(parent) == leaf) {
+                self.replace_extreme(grandparent, false, leaf, next);
+            }
+            if let Some(prev) = self.leaf(leaf).prev.filter(|_| self.rightmost_leaf(parent) == leaf) {
+                self.replace_extreme(grandparent, true, leaf, prev);
+            }
+
+            // the node referencing the leaf moves over to the reference of the parent, which is
+            // below it as well
+            if let NodeKind::Internal { reference, .. } = self.nodes[parent].kind {
+                if reference != leaf {
+                    let owner = self.leaf(leaf).owner;
+                    if let Some(owner) = owner {
+                        if let NodeKind::Internal { reference: owner_reference, .. } = &mut self.nodes[owner].kind {
+                            *owner_reference = reference;
+                        }
+                    }
+                    self.leaf_mut(reference).owner = owner;
+                }
+            }
+            self.nodes.free(parent);
+        }
+        else {
+            self.root = None;
+        }
+
+        let (prev, next) = (self.leaf(leaf).prev, self.leaf(leaf).next);
+        match prev {
+            Some(prev) => self.leaf_mut(prev).next = next,
+            None => self.first_leaf = next,
+        }
+        match next {
+            Some(next) => self.leaf_mut(next).prev = prev,
+            None => self.last_leaf = prev,
+        }
+        self.len -= 1;
+        match self.nodes.free(leaf).kind {
+            NodeKind::Leaf(leaf) => Some(leaf.value),
+            NodeKind::Internal { .. } => unreachable!("expected a leaf"),
+        }
+    }
+
+    /// Returns the smallest key more than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn successor(&self, key: &[u8]) -> Option<(&[u8], &V)> {
+        let (exit, prefix_len) = self.exit_node(key)?;
+        let node = if prefix_len == bits_len(key) {
+            Some(exit)
+        }
+        else if bit(key, prefix_len) {
+            self.leaf(self.rightmost_leaf(exit)).next
+        }
+        else {
+            Some(self.leftmost_leaf(exit))
+        };
+        node.map(|node| self.key_value(node))
+    }
+
+    /// Returns the largest key less than or equal to `key` along with its value. In case of no such key it returns None.
+    pub fn predecessor(&self, key: &[u8]) -> Option<(&[u8], &V)> {
+        let (exit, prefix_len) = self.exit_node(key)?;
+        let node = if prefix_len == bits_len(key) {
+            Some(exit)
+        }
+        else if bit(key, prefix_len) {
+            Some(self.rightmost_leaf(exit))
+        }
+        else {
+            self.leaf(self.leftmost_leaf(exit)).prev
+        };
+        node.map(|node| self.key_value(node))
+    }
+
+    /// Returns the smallest key of the trie along with its value
+    pub fn first_key_value(&self) -> Option<(&[u8], &V)> {
+        self.first_leaf.map(|node| self.key_value(node))
+    }
+
+    /// Returns the largest key of the trie along with its value
+    pub fn last_key_value(&self) -> Option<(&[u8], &V)> {
+        self.last_leaf.map(|node| self.key_value(node))
+    }
+
+    /// Returns an iterator around all the key-value pairs stored in the trie, in lexicographic order of keys.
+    /// # Examples
+    /// ```
+    ///     use xfast::ZfastTrie;
+    ///
+    ///     let mut test_trie: ZfastTrie<()> = ZfastTrie::new();
+    ///     for key in [&b"b"[..], b"ab", b"", b"a"].iter() {
+    ///         test_trie.insert(key, ());
+    ///     }
+    ///     let keys: Vec<&[u8]> = test_trie.iter().map(|(key, _)| key).collect();
+    ///     assert_eq!(keys, vec![&b""[..], b"a", b"ab", b"b"]);
+    /// ```
+    pub fn iter(&self) -> ZfastIter<'_, V> {
+        ZfastIter {
+            trie: self,
+            front: self.first_leaf,
+            back: self.last_leaf,
+            len: self.len,
+        }
+    }
+}
+
+impl<V> Default for ZfastTrie<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Double-ended iterator around the ZfastTrie key and value pairs in lexicographic order of keys
+pub struct ZfastIter<'a, V> {
+    trie: &'a ZfastTrie<V>,
+    // next leaves to be yielded from either end
+    front: Option<Node>,
+    back: Option<Node>,
+    // count of pairs left to be yielded
+    len: usize,
+}
+
+impl<'a, V> Iterator for ZfastIter<'a, V> {
+    type Item = (&'a [u8], &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = self.front?;
+        self.len -= 1;
+        self.front = self.trie.leaf(node).next;
+        Some(self.trie.key_value(node))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, V> DoubleEndedIterator for ZfastIter<'a, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let node = self.back?;
+        self.len -= 1;
+        self.back = self.trie.leaf(node).prev;
+        Some(self.trie.key_value(node))
+    }
+}
+
+impl<'a, V> ExactSizeIterator for ZfastIter<'a, V> {}
+
+impl<'a, V> FusedIterator for ZfastIter<'a, V> {}
+
+impl<'a, V> IntoIterator for &'a ZfastTrie<V> {
+    type Item = (&'a [u8], &'a V);
+    type IntoIter = ZfastIter<'a, V>;
+    fn into_iter(self) -> ZfastIter<'a, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{two_fattest, NodeKind, ZfastTrie};
+
+    fn init() -> ZfastTrie<usize> {
+        let mut test_trie = ZfastTrie::new();
+        for (value, key) in ["/", "/docs", "/docs/intro", "/docs/api", "/index.html", "/img/logo.png"].iter().enumerate() {
+            test_trie.insert(key.as_bytes(), value);
+        }
+        test_trie
+    }
+
+    #[test]
+    fn fattest_numbers() -> Result<(), String> {
+        let fattest: Vec<usize> = [(0, 5), (1, 1), (3, 7), (5, 7), (9, 15), (17, 40)].iter().map(|&(low, high)| two_fattest(low, high)).collect();
+        if fattest == vec![0, 1, 4, 6, 12, 32] {
+            Ok(())
+        }
+        else {
+            Err(format!("2-fattest numbers are wrong, got {:?}", fattest))
+        }
+    }
+
+    #[test]
+    fn lexicographic_neighbours() -> Result<(), String> {
+        let test_trie = init();
+        let successors: Vec<Option<&[u8]>> = ["/docs/", "/docs/b", "/e", "/z", ""].iter()
+            .map(|key| test_trie.successor(key.as_bytes()).map(|(key, _)| key)).collect();
+        let predecessors: Vec<Option<&[u8]>> = ["/docs/", "/docs/b", "/e", "/z", ""].iter()
+            .map(|key| test_trie.predecessor(key.as_bytes()).map(|(key, _)| key)).collect();
+        if successors == vec![Some(&b"/docs/api"[..]), Some(b"/docs/intro"), Some(b"/img/logo.png"), None, Some(b"/")]
+            && predecessors == vec![Some(&b"/docs"[..]), Some(b"/docs/api"), Some(b"/docs/intro"), Some(b"/index.html"), None] {
+            Ok(())
+        }
+        else {
+            Err(format!("Neighbours are wrong, got {:?} and {:?}", successors, predecessors))
+        }
+    }
+
+    #[test]
+    fn prefixes_of_keys() -> Result<(), String> {
+        let mut test_trie = init();
+        test_trie.remove(b"/docs");
+        test_trie.insert(b"/docs/intro/", 6);
+        let keys: Vec<&[u8]> = test_trie.iter().map(|(key, _)| key).collect();
+        if keys == vec![&b"/"[..], b"/docs/api", b"/docs/intro", b"/docs/intro/", b"/img/logo.png", b"/index.html"]
+            && test_trie.get(b"/docs").is_none() && test_trie.get(b"/docs/intro") == Some(&2)
+            && test_trie.longest_common_prefix(b"/docs") == b"/docs" && test_trie.longest_common_prefix(b"/ix") == b"/i" {
+            Ok(())
+        }
+        else {
+            Err(format!("Keys which are prefixes of others are mixed up, got {:?}", keys))
+        }
+    }
+
+    #[test]
+    fn remove_all() -> Result<(), String> {
+        let mut test_trie = init();
+        let keys: Vec<Vec<u8>> = test_trie.iter().map(|(key, _)| key.to_vec()).collect();
+        for key in keys.iter() {
+            test_trie.remove(key);
+        }
+        test_trie.insert(b"key", 1);
+        if test_trie.len() == 1 && test_trie.successor(b"") == Some((&b"key"[..], &1)) && test_trie.handle_maps.iter().all(|handles| handles.is_empty()) {
+            Ok(())
+        }
+        else {
+            Err(String::from("The trie should be reusable once emptied"))
+        }
+    }
+
+    #[test]
+    fn extremes_of_nested_keys() -> Result<(), String> {
+        // nested keys make a chain of internal nodes as deep as the count of keys
+        let keys: Vec<Vec<u8>> = (0..300).map(|len| vec![b'a'; len * 7 % 300]).chain((0..40).map(|len| {
+            let mut key = vec![b'a'; len * 5];
+            key.push(b'b');
+            key
+        })).collect();
+        let mut test_trie = ZfastTrie::new();
+        let mut expected = BTreeMap::new();
+        for (value, key) in keys.iter().enumerate() {
+            test_trie.insert(key, value);
+            expected.insert(key.clone(), value);
+        }
+        for key in keys.iter().step_by(3).chain(Some(&vec![b'a'; 299])) {
+            test_trie.remove(key);
+            expected.remove(key);
+        }
+
+        // compare the extremes kept by every internal node with the ends of its subtree
+        let walk = |mut node, side: usize| {
+            while let NodeKind::Internal { children, .. } = &test_trie.nodes[node].kind {
+                node = children[side];
+            }
+            node
+        };
+        let mut internal_nodes: Vec<_> = test_trie.root.into_iter().collect();
+        let mut consistent = true;
+        while let Some(node) = internal_nodes.pop() {
+            if let NodeKind::Internal { children, extremes, .. } = &test_trie.nodes[node].kind {
+                consistent &= *extremes == [walk(node, 0), walk(node, 1)];
+                internal_nodes.extend_from_slice(children);
+            }
+        }
+        let neighbours = keys.iter().map(|key| [&key[..], &key[..key.len() / 2]].concat()).all(|query| {
+            test_trie.successor(&query) == expected.range(query.clone()..).next().map(|(key, value)| (&key[..], value))
+                && test_trie.predecessor(&query) == expected.range(..=query.clone()).next_back().map(|(key, value)| (&key[..], value))
+        });
+        if consistent && neighbours {
+            Ok(())
+        }
+        else {
+            Err(format!("Extremes of the internal nodes are stale, consistent: {}", consistent))
+        }
+    }
+}